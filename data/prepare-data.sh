@@ -13,6 +13,12 @@ curl 'https://rtw-api.oneworld.com/api/MapData' \
 curl https://raw.githubusercontent.com/lukes/ISO-3166-Countries-with-Regional-Codes/master/all/all.json \
      -o country-code.json
 
+# Each route file (routes/<airport>.json) is a JSON array of the destinations of the airport:
+#   [{"iata": "LHR", "lat": 51.47, "lon": -0.45, "airlines": ["BA", "JL"]}, ...]
+# where "lat" and "lon" (coordinates of the destination airport) and "airlines" (IATA codes of the
+# operating airlines) are optional. The route lists of Flightradar24 have no airlines, so "airlines"
+# must be added from another source for the routes to be checked against the eligible carriers;
+# the routes without airlines are assumed to be flown by an eligible carrier (see check-data).
 download_fr24() {
     for AIRPORT in $*; do
         curl -q https://www.flightradar24.com/data/airports/${AIRPORT}/routes | grep -o 'arrRoutes=\(\[.\+\]\)' | cut -c 11- > "${OUTPUT_DIR}/routes/${AIRPORT}.json"
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use serde::Deserialize;
+use super::AreaCode;
+use super::error::DataError;
+use super::json::{JsonCityData, JsonCountryCode};
+
+// File in the data directory overriding the rules of the area classification
+const RULES_FILE: &str = "continent-rules.json";
+// Rules of oneworld, used if the data directory has no rules file
+const DEFAULT_RULES: &str = include_str!("../../data/continent-rules.json");
+
+// Rule assigning an area to the cities matching all the given conditions.
+// The conditions are matched against the city code, the country code, the regions of the country
+// in country-code.json and the time zone of the city in the map data.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AreaRule {
+    #[serde(default)]
+    cities: Vec<String>,
+    #[serde(default)]
+    countries: Vec<String>,
+    region: Option<String>,
+    sub_region: Option<String>,
+    intermediate_region: Option<String>,
+    #[serde(default)]
+    time_zones: Vec<String>,
+    area: AreaCode
+}
+impl AreaRule {
+    fn matches(&self, city: &JsonCityData, country: &JsonCountryCode) -> bool {
+        let list_matches = |list: &[String], value: &str| list.is_empty() || list.iter().any(|v| v == value);
+        let option_matches = |option: &Option<String>, value: &str| option.as_ref().is_none_or(|v| v == value);
+
+        list_matches(&self.cities, &city.cityCode) &&
+            list_matches(&self.countries, &country.code) &&
+            option_matches(&self.region, &country.region) &&
+            option_matches(&self.sub_region, &country.sub_region) &&
+            option_matches(&self.intermediate_region, &country.intermediate_region) &&
+            list_matches(&self.time_zones, &city.timeZone)
+    }
+}
+
+// Ordered rules of the area classification, where the first matching rule applies
+pub struct AreaRules {
+    rules: Vec<AreaRule>,
+    // Hash of the rules file, which identifies the rules in the dataset cache
+    pub hash: u64
+}
+impl AreaRules {
+    pub fn load(data_dir: &str) -> Result<AreaRules, DataError> {
+        let path = Path::new(data_dir).join(RULES_FILE);
+        if !path.exists() {
+            return AreaRules::parse(DEFAULT_RULES, RULES_FILE);
+        }
+
+        let path = path.to_string_lossy().into_owned();
+        let text = fs::read_to_string(&path).map_err(|source| DataError::MissingFile { path: path.clone(), source })?;
+        AreaRules::parse(&text, &path)
+    }
+
+    fn parse(text: &str, path: &str) -> Result<AreaRules, DataError> {
+        let rules = serde_json::from_str(text).map_err(|e| DataError::MalformedJson { path: String::from(path), message: e.to_string() })?;
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+
+        Ok(AreaRules { rules, hash: hasher.finish() })
+    }
+
+    pub fn find_area(&self, city: &JsonCityData, country_map: &HashMap<String, &JsonCountryCode>, path: &str) -> Result<AreaCode, DataError> {
+        let country = match country_map.get(&city.countryCode) {
+            Some(country) => country,
+            None => return Err(DataError::UnknownCountryCode {
+                path: String::from(path), city: city.cityCode.clone(), country: city.countryCode.clone()
+            })
+        };
+
+        match self.rules.iter().find(|r| r.matches(city, country)) {
+            Some(rule) => Ok(rule.area),
+            None => Err(DataError::UnknownRegion {
+                path: String::from(path), city: city.cityCode.clone(), country: country.code.clone(), region: country.region.clone()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn country(code: &str, region: &str, sub_region: &str, intermediate_region: &str) -> JsonCountryCode {
+        JsonCountryCode {
+            code: String::from(code),
+            region: String::from(region),
+            sub_region: String::from(sub_region),
+            intermediate_region: String::from(intermediate_region)
+        }
+    }
+
+    fn city(code: &str, country: &str, time_zone: &str) -> JsonCityData {
+        JsonCityData {
+            cityCode: String::from(code),
+            countryCode: String::from(country),
+            lat: String::from("0"),
+            lon: String::from("0"),
+            timeZone: String::from(time_zone)
+        }
+    }
+
+    fn countries() -> Vec<JsonCountryCode> {
+        vec![
+            country("JP", "Asia", "Eastern Asia", ""),
+            country("CN", "Asia", "Eastern Asia", ""),
+            country("IN", "Asia", "Southern Asia", ""),
+            country("IR", "Asia", "Southern Asia", ""),
+            country("QA", "Asia", "Western Asia", ""),
+            country("CY", "Asia", "Western Asia", ""),
+            country("KZ", "Asia", "Central Asia", ""),
+            country("AU", "Oceania", "Australia and New Zealand", ""),
+            country("GU", "Oceania", "Micronesia", ""),
+            country("GB", "Europe", "Northern Europe", ""),
+            country("RU", "Europe", "Eastern Europe", ""),
+            country("DZ", "Africa", "Northern Africa", ""),
+            country("MA", "Africa", "Northern Africa", ""),
+            country("EG", "Africa", "Northern Africa", ""),
+            country("LY", "Africa", "Northern Africa", ""),
+            country("SD", "Africa", "Northern Africa", ""),
+            country("ZA", "Africa", "Sub-Saharan Africa", "Southern Africa"),
+            country("US", "Americas", "Northern America", ""),
+            country("MX", "Americas", "Latin America and the Caribbean", "Central America"),
+            country("JM", "Americas", "Latin America and the Caribbean", "Caribbean"),
+            country("BR", "Americas", "Latin America and the Caribbean", "South America"),
+            country("AQ", "", "", "")
+        ]
+    }
+
+    fn find_area(rules: &AreaRules, code: &str, country: &str, time_zone: &str) -> Option<AreaCode> {
+        let countries = countries();
+        let country_map = countries.iter().map(|c| (c.code.clone(), c)).collect();
+
+        rules.find_area(&city(code, country, time_zone), &country_map, "test").ok()
+    }
+
+    #[test]
+    fn default_rules() {
+        let rules = AreaRules::parse(DEFAULT_RULES, RULES_FILE).unwrap();
+        let cases = [
+            ("HND", "JP", "9", AreaCode::Japan),
+            ("PEK", "CN", "8", AreaCode::Asia),
+            ("DEL", "IN", "5.5", AreaCode::Asia),
+            ("KZN", "KZ", "6", AreaCode::Asia),
+            ("THR", "IR", "3.5", AreaCode::EuropeMiddleEast),
+            ("DOH", "QA", "3", AreaCode::EuropeMiddleEast),
+            ("LCA", "CY", "2", AreaCode::EuropeMiddleEast),
+            ("SYD", "AU", "10", AreaCode::Oceania),
+            ("GUM", "GU", "10", AreaCode::Oceania),
+            ("LHR", "GB", "0", AreaCode::EuropeMiddleEast),
+            ("SVO", "RU", "3", AreaCode::EuropeMiddleEast),
+            ("OVB", "RU", "7", AreaCode::Asia),
+            ("VVO", "RU", "10", AreaCode::Asia),
+            ("PKC", "RU", "12", AreaCode::EuropeMiddleEast),
+            ("ALG", "DZ", "1", AreaCode::EuropeMiddleEast),
+            ("CMN", "MA", "0", AreaCode::EuropeMiddleEast),
+            ("CAI", "EG", "2", AreaCode::EuropeMiddleEast),
+            ("TIP", "LY", "2", AreaCode::EuropeMiddleEast),
+            ("KRT", "SD", "2", AreaCode::EuropeMiddleEast),
+            ("JNB", "ZA", "2", AreaCode::Africa),
+            ("JFK", "US", "-5", AreaCode::NorthAmerica),
+            ("HNL", "US", "-10", AreaCode::NorthAmerica),
+            ("MEX", "MX", "-6", AreaCode::NorthAmerica),
+            ("KIN", "JM", "-5", AreaCode::NorthAmerica),
+            ("GRU", "BR", "-3", AreaCode::SouthAmerica)
+        ];
+
+        for (code, country, time_zone, area) in &cases {
+            assert_eq!(find_area(&rules, code, country, time_zone), Some(*area), "{} ({})", code, country);
+        }
+    }
+
+    #[test]
+    fn unknown_country_and_region() {
+        let rules = AreaRules::parse(DEFAULT_RULES, RULES_FILE).unwrap();
+
+        assert_eq!(find_area(&rules, "XXX", "XX", "0"), None);
+        assert_eq!(find_area(&rules, "TNM", "AQ", "0"), None);
+    }
+
+    #[test]
+    fn custom_rules() {
+        let rules = AreaRules::parse(r#"[
+            {"cities": ["GUM"], "area": "Asia"},
+            {"countries": ["KZ"], "time_zones": ["5"], "area": "EuropeMiddleEast"},
+            {"region": "Asia", "area": "Asia"},
+            {"region": "Oceania", "area": "Oceania"}
+        ]"#, "test").unwrap();
+
+        assert_eq!(find_area(&rules, "GUM", "GU", "10"), Some(AreaCode::Asia));
+        assert_eq!(find_area(&rules, "ROR", "GU", "9"), Some(AreaCode::Oceania));
+        assert_eq!(find_area(&rules, "GUW", "KZ", "5"), Some(AreaCode::EuropeMiddleEast));
+        assert_eq!(find_area(&rules, "ALA", "KZ", "6"), Some(AreaCode::Asia));
+        assert_eq!(find_area(&rules, "LHR", "GB", "0"), None);
+    }
+
+    #[test]
+    fn malformed_rules() {
+        assert!(AreaRules::parse(r#"[{"country": "JP", "area": "Japan"}]"#, "test").is_err());
+        assert!(AreaRules::parse(r#"[{"area": "Antarctica"}]"#, "test").is_err());
+    }
+}
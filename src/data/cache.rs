@@ -0,0 +1,234 @@
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use super::{AreaCode, City, Route};
+
+// Compiled form of the dataset, so that the JSON files need not be parsed on every run.
+// The points are not stored as they depend on the program and the booking class.
+//
+// Layout (little endian):
+//   magic, version, hash of the area rules,
+//   source files: count, (path relative to the data directory, size, modified time in ns) ...
+//   cities: count, (area, lon, lat, code, metro, country, hub,
+//                   routes: count, (to, airlines: count, (airline) ...) ...) ...
+const MAGIC: &[u8; 4] = b"OWEC";
+const VERSION: u32 = 4;
+
+const AREAS: [AreaCode; 7] = [
+    AreaCode::Japan, AreaCode::Asia, AreaCode::EuropeMiddleEast, AreaCode::Oceania,
+    AreaCode::Africa, AreaCode::NorthAmerica, AreaCode::SouthAmerica
+];
+
+// Size and modified time of the file, which change when the file is updated
+fn fingerprint(data_dir: &str, path: &str) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(Path::new(data_dir).join(path)).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    Some((metadata.len(), modified.as_nanos() as u64))
+}
+
+struct Writer {
+    buf: Vec<u8>
+}
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+    fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn f64(&mut self, v: f64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    fn str(&mut self, v: &str) {
+        self.u32(v.len() as u32);
+        self.buf.extend_from_slice(v.as_bytes());
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8]
+}
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < len {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+
+        Some(head)
+    }
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes(8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+    fn f64(&mut self) -> Option<f64> {
+        self.bytes(8).map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+    }
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+}
+
+// Loads the cities and the routes from the cache, unless it is missing, broken or older than any of the sources
+pub fn load(data_dir: &str, path: &str, rules_hash: u64) -> Option<Vec<City>> {
+    let buf = fs::read(path).ok()?;
+    let mut r = Reader { buf: &buf };
+
+    if r.bytes(4)? != MAGIC || r.u32()? != VERSION || r.u64()? != rules_hash {
+        return None;
+    }
+
+    for _ in 0..r.u32()? {
+        let source = r.str()?;
+        let size = r.u64()?;
+        let modified = r.u64()?;
+        if fingerprint(data_dir, &source)? != (size, modified) {
+            return None;
+        }
+    }
+
+    let count = r.u32()? as usize;
+    let mut cities = Vec::with_capacity(count);
+    for index in 0..count {
+        let mut city = City {
+            area: *AREAS.get(r.u8()? as usize)?,
+            lon: r.f64()?,
+            lat: r.f64()?,
+            code: r.str()?,
+            metro: r.str()?,
+            country: r.str()?,
+            hub: r.u8()? != 0,
+            routes: Vec::new(),
+            index
+        };
+
+        for _ in 0..r.u32()? {
+            let to = r.u32()? as usize;
+            if to >= count {
+                return None;
+            }
+            let airlines = (0..r.u32()?).map(|_| r.str()).collect::<Option<Vec<String>>>()?;
+            city.routes.push(Route { to, points: 0, airlines });
+        }
+        cities.push(city);
+    }
+
+    Some(cities)
+}
+
+// Saves the cities and the routes loaded from the sources. Failures are ignored
+// since the cache is only an optimization (e.g. the data directory may be read-only).
+pub fn save(data_dir: &str, path: &str, rules_hash: u64, sources: &[String], cities: &[City]) {
+    let mut w = Writer { buf: Vec::new() };
+
+    w.buf.extend_from_slice(MAGIC);
+    w.u32(VERSION);
+    w.u64(rules_hash);
+
+    w.u32(sources.len() as u32);
+    for source in sources {
+        let (size, modified) = match fingerprint(data_dir, source) {
+            Some(f) => f,
+            None => return
+        };
+        w.str(source);
+        w.u64(size);
+        w.u64(modified);
+    }
+
+    w.u32(cities.len() as u32);
+    for city in cities {
+        w.u8(AREAS.iter().position(|a| *a == city.area).unwrap() as u8);
+        w.f64(city.lon);
+        w.f64(city.lat);
+        w.str(&city.code);
+        w.str(&city.metro);
+        w.str(&city.country);
+        w.u8(city.hub as u8);
+
+        w.u32(city.routes.len() as u32);
+        for route in &city.routes {
+            w.u32(route.to as u32);
+            w.u32(route.airlines.len() as u32);
+            for airline in &route.airlines {
+                w.str(airline);
+            }
+        }
+    }
+
+    // Written to a temporary file first, so that other runs never read a half-written cache
+    let tmp_path = format!("{}.tmp", path);
+    if fs::write(&tmp_path, &w.buf).is_ok() {
+        let _ = fs::rename(&tmp_path, path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::Duration;
+    use super::*;
+    use crate::data::{fixture, CityData};
+    use crate::data::area::AreaRules;
+
+    #[test]
+    fn round_trip() {
+        let dir = fixture::data_dir("cache-round-trip");
+        let rules = AreaRules::load(&dir.path).unwrap();
+        let (cities, sources) = CityData::load_sources(&dir.path, &rules).unwrap();
+        let path = dir.file("owe-cache.bin");
+
+        save(&dir.path, &path, rules.hash, &sources, &cities);
+        let loaded = load(&dir.path, &path, rules.hash).unwrap();
+
+        assert_eq!(loaded.len(), 6);
+        assert_eq!(fixture::summary(&loaded), fixture::summary(&cities));
+        assert!(loaded.iter().enumerate().all(|(i, c)| c.index == i));
+    }
+
+    #[test]
+    fn invalidation() {
+        let dir = fixture::data_dir("cache-invalidation");
+        let rules = AreaRules::load(&dir.path).unwrap();
+        let (cities, sources) = CityData::load_sources(&dir.path, &rules).unwrap();
+        let path = dir.file("owe-cache.bin");
+        save(&dir.path, &path, rules.hash, &sources, &cities);
+        let buf = fs::read(&path).unwrap();
+
+        // Different area rules
+        assert!(load(&dir.path, &path, rules.hash + 1).is_none());
+
+        // Truncated cache
+        for len in &[0, 4, 8, 16, 20, buf.len() / 2, buf.len() - 1] {
+            fs::write(&path, &buf[..*len]).unwrap();
+            assert!(load(&dir.path, &path, rules.hash).is_none(), "{}", len);
+        }
+        fs::write(&path, &buf).unwrap();
+        assert!(load(&dir.path, &path, rules.hash).is_some());
+
+        // Source file updated without changing the size
+        let route_file = File::options().write(true).open(dir.file("routes/hkg.json")).unwrap();
+        let modified = route_file.metadata().unwrap().modified().unwrap();
+        route_file.set_modified(modified + Duration::from_secs(10)).unwrap();
+        assert!(load(&dir.path, &path, rules.hash).is_none());
+        route_file.set_modified(modified).unwrap();
+        assert!(load(&dir.path, &path, rules.hash).is_some());
+
+        // Source file of a different size
+        let map_data = fs::read_to_string(dir.file("owe-map-data.json")).unwrap();
+        dir.write("owe-map-data.json", &format!("{}\n", map_data));
+        assert!(load(&dir.path, &path, rules.hash).is_none());
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::Path;
+use super::City;
+use super::error::DataError;
+use super::json;
+
+// File in the data directory overriding the carriers eligible on the fare (a JSON array of IATA airline codes)
+const CARRIERS_FILE: &str = "carriers.json";
+
+// oneworld member airlines and their affiliates
+const DEFAULT_CARRIERS: [&str; 26] = [
+    // Members
+    "AA", "AS", "AT", "AY", "BA", "CX", "FJ", "IB", "JL", "MH", "QF", "QR", "RJ", "UL", "WY",
+    // Affiliates
+    "3X", "CJ", "I2", "MQ", "N7", "NU", "OH", "PT", "QX", "XM", "YW"
+];
+
+// Airlines whose flights can be booked on the oneworld Explorer fare
+pub fn load_carriers(data_dir: &str) -> Result<Vec<String>, DataError> {
+    let path = Path::new(data_dir).join(CARRIERS_FILE);
+
+    if path.exists() {
+        json::load_from_json_file(&path.to_string_lossy())
+    } else {
+        Ok(DEFAULT_CARRIERS.iter().map(|c| String::from(*c)).collect())
+    }
+}
+
+// Excludes the routes only flown by the carriers not eligible on the fare, and the other carriers from the routes.
+// The routes whose airlines are unknown are kept, and their number is returned to be reported.
+pub fn exclude_routes(cities: &mut [City], carriers: &[String]) -> usize {
+    let mut unknown_airlines = 0;
+    for city in cities {
+        city.routes.retain_mut(|r| {
+            let known = !r.airlines.is_empty();
+            r.airlines.retain(|a| carriers.contains(a));
+            if !known {
+                unknown_airlines += 1;
+            }
+            !known || !r.airlines.is_empty()
+        });
+    }
+
+    unknown_airlines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{fixture, CityData};
+    use crate::data::area::AreaRules;
+
+    // Routes of each airport with their airlines, and the number of the routes whose airlines are unknown
+    fn load(dir: &fixture::DataDir) -> (Vec<String>, usize) {
+        let rules = AreaRules::load(&dir.path).unwrap();
+        let (mut cities, _) = CityData::load_sources(&dir.path, &rules).unwrap();
+        let unknown_airlines = exclude_routes(&mut cities, &load_carriers(&dir.path).unwrap());
+
+        (cities.iter().map(|c| {
+            let routes: Vec<String> = c.routes.iter().map(|r| format!("{}:{}", cities[r.to].code, r.airlines.join(","))).collect();
+            format!("{} {}", c.code, routes.join(" "))
+        }).collect(), unknown_airlines)
+    }
+
+    #[test]
+    fn default_carriers() {
+        let dir = fixture::data_dir("carriers-default");
+
+        // UA is not a oneworld carrier, and the airlines of HKG - LHR are unknown
+        assert_eq!(load(&dir), (vec![
+            String::from("HND LHR:BA,JL HKG:CX,JL DFW:AA,JL"),
+            String::from("NRT LHR:BA SYD:JL,QF"),
+            String::from("LHR HND:BA,JL HKG:BA,CX DFW:AA,BA"),
+            String::from("HKG HND:CX LHR: SYD:CX,QF"),
+            String::from("DFW HND:AA,JL LHR:AA,BA"),
+            String::from("SYD NRT:JL,QF HKG:CX,QF")
+        ], 1));
+    }
+
+    #[test]
+    fn custom_carriers() {
+        let dir = fixture::data_dir("carriers-custom");
+        dir.write("carriers.json", r#"["JL", "UA"]"#);
+
+        assert_eq!(load(&dir), (vec![
+            String::from("HND LHR:JL HKG:JL DFW:JL"),
+            String::from("NRT DFW:UA SYD:JL"),
+            String::from("LHR HND:JL"),
+            String::from("HKG LHR:"),
+            String::from("DFW HND:JL NRT:UA"),
+            String::from("SYD NRT:JL")
+        ], 1));
+
+        dir.write("carriers.json", r#"{"carriers": ["JL"]}"#);
+        assert!(load_carriers(&dir.path).is_err());
+    }
+}
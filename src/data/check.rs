@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use serde::Serialize;
+use super::area::AreaRules;
+use super::error::DataError;
+use super::json;
+
+#[derive(PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // Broken data, which may make the loading fail
+    Error,
+    // The dataset can be loaded, but part of it is ignored
+    Warning
+}
+
+pub enum DataIssue {
+    MissingRouteFile { hub: String, airport: String, path: String },
+    MalformedRouteFile { path: String, message: String },
+    DuplicateHub(String),
+    UnknownDestination { airport: String, destination: String },
+    UnknownCountryCode { city: String, country: String },
+    UnknownRegion { city: String, country: String, region: String },
+    BadCoordinates { city: String, value: String },
+    UnreachableCity(String),
+    MissingAirlines { routes: usize, total: usize }
+}
+impl DataIssue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            DataIssue::DuplicateHub(_) | DataIssue::UnknownDestination { .. } | DataIssue::UnreachableCity(_) |
+                DataIssue::MissingAirlines { .. } => Severity::Warning,
+            _ => Severity::Error
+        }
+    }
+}
+impl fmt::Display for DataIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataIssue::MissingRouteFile { hub, airport, path } =>
+                write!(f, "route file for airport {} of hub {} is missing: {}", airport, hub, path),
+            DataIssue::MalformedRouteFile { path, message } => write!(f, "{}: malformed JSON: {}", path, message),
+            DataIssue::DuplicateHub(hub) => write!(f, "hub {} is listed more than once (the last entry is used)", hub),
+            DataIssue::UnknownDestination { airport, destination } =>
+                write!(f, "destination {} of airport {} is not in the map data", destination, airport),
+            DataIssue::UnknownCountryCode { city, country } => write!(f, "city {} has an unknown country code {}", city, country),
+            DataIssue::UnknownRegion { city, country, region } =>
+                write!(f, "cannot determine the area for city {} in {} (region \"{}\")", city, country, region),
+            DataIssue::BadCoordinates { city, value } => write!(f, "city {} has a bad coordinate \"{}\"", city, value),
+            DataIssue::UnreachableCity(city) => write!(f, "city {} is not reachable from any hub", city),
+            DataIssue::MissingAirlines { routes, total } =>
+                write!(f, "{} of {} routes have no airline data and cannot be checked against the eligible carriers", routes, total)
+        }
+    }
+}
+
+// Checks the dataset and reports all the problems found. Only the errors which prevent
+// the check itself (e.g. a missing map data file) are returned as `DataError`.
+pub fn check_data(data_dir: &str) -> Result<Vec<DataIssue>, DataError> {
+    let path = |name: &str| Path::new(data_dir).join(name).to_string_lossy().into_owned();
+    let mut issues = Vec::new();
+
+    let country_code = json::JsonCountryCode::load_from_file(&path("country-code.json"))?;
+    let map_data: json::JsonMapData = json::load_from_json_file(&path("owe-map-data.json"))?;
+    let hubs: Vec<json::JsonHubInfo> = json::load_from_json_file(&path("hub-info.json"))?;
+    let rules = AreaRules::load(data_dir)?;
+
+    let map_cities: HashSet<&str> = map_data.mapData.cities.city.iter().map(|c| c.cityCode.as_str()).collect();
+    let mut hub_set = HashSet::new();
+    let mut hub_airports = HashSet::new();
+    let mut destinations = HashSet::new();
+    let mut total_routes = 0;
+    let mut missing_airlines = 0;
+
+    for hub in &hubs {
+        // The airports of a duplicate are still checked, as the last entry is used when the dataset is loaded
+        if !hub_set.insert(hub.city_code.as_str()) {
+            issues.push(DataIssue::DuplicateHub(hub.city_code.clone()));
+        }
+
+        for airport in &hub.airports {
+            if !hub_airports.insert(airport.as_str()) {
+                continue;
+            }
+
+            let route_path = path(&format!("routes/{}.json", airport.to_lowercase()));
+            let route: Vec<json::JsonFr24Route> = match json::load_from_json_file(&route_path) {
+                Ok(route) => route,
+                Err(DataError::MissingFile { path, .. }) => {
+                    issues.push(DataIssue::MissingRouteFile { hub: hub.city_code.clone(), airport: airport.clone(), path });
+                    continue;
+                },
+                Err(DataError::MalformedJson { path, message }) => {
+                    issues.push(DataIssue::MalformedRouteFile { path, message });
+                    continue;
+                },
+                Err(e) => return Err(e)
+            };
+
+            let mut unknown = Vec::new();
+            for r in route {
+                total_routes += 1;
+                if r.airlines.is_empty() {
+                    missing_airlines += 1;
+                }
+                if let Some(ap) = r.iata {
+                    if !map_cities.contains(ap.as_str()) && !hubs.iter().any(|h| h.airports.contains(&ap)) && !unknown.contains(&ap) {
+                        unknown.push(ap.clone());
+                    }
+                    destinations.insert(ap);
+                }
+            }
+            unknown.sort();
+            for destination in unknown {
+                issues.push(DataIssue::UnknownDestination { airport: airport.clone(), destination });
+            }
+        }
+    }
+
+    if missing_airlines > 0 {
+        issues.push(DataIssue::MissingAirlines { routes: missing_airlines, total: total_routes });
+    }
+
+    let country_map: HashMap<String, &json::JsonCountryCode> = country_code.iter().map(|c| (c.code.clone(), c)).collect();
+    for city in &map_data.mapData.cities.city {
+        match rules.find_area(city, &country_map, "") {
+            Err(DataError::UnknownCountryCode { .. }) =>
+                issues.push(DataIssue::UnknownCountryCode { city: city.cityCode.clone(), country: city.countryCode.clone() }),
+            Err(DataError::UnknownRegion { city, country, region, .. }) =>
+                issues.push(DataIssue::UnknownRegion { city, country, region }),
+            _ => ()
+        }
+
+        for value in &[&city.lat, &city.lon] {
+            if value.parse::<f64>().is_err() {
+                issues.push(DataIssue::BadCoordinates { city: city.cityCode.clone(), value: String::from(value.as_str()) });
+            }
+        }
+
+        let code = city.cityCode.as_str();
+        if !hub_set.contains(code) && !hub_airports.contains(code) && !destinations.contains(code) {
+            issues.push(DataIssue::UnreachableCity(city.cityCode.clone()));
+        }
+    }
+
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::fixture;
+
+    // Issue without the paths of the temporary directory
+    fn describe(issue: &DataIssue) -> String {
+        match issue {
+            DataIssue::MissingRouteFile { hub, airport, .. } => format!("route file for airport {} of hub {} is missing", airport, hub),
+            DataIssue::MalformedRouteFile { path, .. } => format!("{} is malformed", Path::new(path).file_name().unwrap().to_string_lossy()),
+            _ => issue.to_string()
+        }
+    }
+
+    #[test]
+    fn sample_data() {
+        let dir = fixture::data_dir("check-sample");
+        let issues: Vec<String> = check_data(&dir.path).unwrap().iter().map(describe).collect();
+
+        assert_eq!(issues, vec![
+            "destination XYZ of airport LHR is not in the map data",
+            "1 of 13 routes have no airline data and cannot be checked against the eligible carriers",
+            "city MEL is not reachable from any hub"
+        ]);
+    }
+
+    #[test]
+    fn broken_data() {
+        let dir = fixture::data_dir("check-broken");
+        let countries = fs_read(&dir, "country-code.json").replacen("[", r#"[
+            {"name": "Antarctica", "alpha-2": "AQ", "region": "", "sub-region": "", "intermediate-region": ""},"#, 1);
+        dir.write("country-code.json", &countries);
+        let map_data = fs_read(&dir, "owe-map-data.json").replacen(r#""city": ["#, r#""city": [
+            {"cityCode": "TNM", "countryCode": "AQ", "lat": "-62.19", "lon": "-58.98", "timeZone": "-3", "name": "Teniente Marsh"},
+            {"cityCode": "XXA", "countryCode": "ZZ", "lat": "0", "lon": "0", "timeZone": "0", "name": "Nowhere"},
+            {"cityCode": "BAD", "countryCode": "AU", "lat": "north", "lon": "151", "timeZone": "10", "name": "Bad"},"#, 1);
+        dir.write("owe-map-data.json", &map_data);
+        // The second entry of LON replaces the first one, so its airports must be checked
+        dir.write("hub-info.json", r#"[
+            {"city_code": "TYO", "airports": ["HND", "NRT"]},
+            {"city_code": "LON", "airports": ["LHR"]},
+            {"city_code": "HKG", "airports": ["HKG"]},
+            {"city_code": "LON", "airports": ["LHR", "LGW"]}
+        ]"#);
+        dir.write("routes/nrt.json", r#"[{"iata": "SYD""#);
+
+        let issues = check_data(&dir.path).unwrap();
+        assert_eq!(issues.iter().map(describe).collect::<Vec<String>>(), vec![
+            "nrt.json is malformed",
+            "destination XYZ of airport LHR is not in the map data",
+            "hub LON is listed more than once (the last entry is used)",
+            "route file for airport LGW of hub LON is missing",
+            "1 of 10 routes have no airline data and cannot be checked against the eligible carriers",
+            "cannot determine the area for city TNM in AQ (region \"\")",
+            "city TNM is not reachable from any hub",
+            "city XXA has an unknown country code ZZ",
+            "city XXA is not reachable from any hub",
+            "city BAD has a bad coordinate \"north\"",
+            "city BAD is not reachable from any hub",
+            "city MEL is not reachable from any hub"
+        ]);
+        let errors = issues.iter().filter(|i| i.severity() == Severity::Error).count();
+        assert_eq!(errors, 5);
+    }
+
+    fn fs_read(dir: &fixture::DataDir, name: &str) -> String {
+        std::fs::read_to_string(dir.file(name)).unwrap()
+    }
+}
@@ -0,0 +1,99 @@
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Cabin {
+    First,
+    Business,
+    PremiumEconomy,
+    Economy
+}
+impl Cabin {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cabin::First => "First",
+            Cabin::Business => "Business",
+            Cabin::PremiumEconomy => "Premium Economy",
+            Cabin::Economy => "Economy"
+        }
+    }
+}
+
+// Booking classes (RBD) commonly used by the oneworld carriers for each cabin
+const FIRST_CLASSES: &str = "FAP";
+const BUSINESS_CLASSES: &str = "JCDIR";
+const PREMIUM_ECONOMY_CLASSES: &str = "WE";
+const ECONOMY_CLASSES: &str = "YBHKMLVSNQOGT";
+
+// Booking class (RBD) of a flight segment
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct BookingClass {
+    pub code: char,
+    pub cabin: Cabin
+}
+impl BookingClass {
+    // Accepts either a booking class letter ("D") or a cabin name ("business"),
+    // in which case the booking class of the Explorer fare for the cabin is used
+    pub fn parse(s: &str) -> Option<BookingClass> {
+        let code = match s.to_lowercase().as_str() {
+            "first" => 'A',
+            "business" => 'D',
+            "premium" | "premium-economy" => 'W',
+            "economy" => 'L',
+            _ => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => c.to_ascii_uppercase(),
+                    _ => return None
+                }
+            }
+        };
+
+        let cabin = if FIRST_CLASSES.contains(code) {
+            Cabin::First
+        } else if BUSINESS_CLASSES.contains(code) {
+            Cabin::Business
+        } else if PREMIUM_ECONOMY_CLASSES.contains(code) {
+            Cabin::PremiumEconomy
+        } else if ECONOMY_CLASSES.contains(code) {
+            Cabin::Economy
+        } else {
+            return None;
+        };
+
+        Some(BookingClass { code, cabin })
+    }
+}
+
+// Looks up the value for the booking class from a table of (booking classes, value)
+pub fn lookup<T: Copy>(table: &[(&str, T)], class: &BookingClass) -> Option<T> {
+    table.iter().find(|(classes, _)| classes.contains(class.code)).map(|(_, v)| *v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parse = |s| BookingClass::parse(s).map(|c| (c.code, c.cabin));
+        assert_eq!(parse("first"), Some(('A', Cabin::First)));
+        assert_eq!(parse("Business"), Some(('D', Cabin::Business)));
+        assert_eq!(parse("premium"), Some(('W', Cabin::PremiumEconomy)));
+        assert_eq!(parse("premium-economy"), Some(('W', Cabin::PremiumEconomy)));
+        assert_eq!(parse("ECONOMY"), Some(('L', Cabin::Economy)));
+        assert_eq!(parse("J"), Some(('J', Cabin::Business)));
+        assert_eq!(parse("e"), Some(('E', Cabin::PremiumEconomy)));
+        assert_eq!(parse("t"), Some(('T', Cabin::Economy)));
+
+        assert_eq!(parse("Z"), None);
+        assert_eq!(parse("JD"), None);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("coach"), None);
+    }
+
+    #[test]
+    fn lookup_table() {
+        let table = [("FA", 1), ("JCD", 2)];
+        assert_eq!(lookup(&table, &BookingClass::parse("A").unwrap()), Some(1));
+        assert_eq!(lookup(&table, &BookingClass::parse("business").unwrap()), Some(2));
+        assert_eq!(lookup(&table, &BookingClass::parse("Y").unwrap()), None);
+    }
+}
@@ -0,0 +1,134 @@
+use std::f64::consts::PI;
+
+// Radius of the earth in mile
+const RADIUS_MILE: f64 = 3958.756;
+
+// WGS-84 ellipsoid (semi-major axis in metre and flattening)
+const WGS84_A: f64 = 6378137.0;
+const WGS84_F: f64 = 1.0 / 298.257223563;
+const METRE_PER_MILE: f64 = 1609.344;
+
+// Model of the earth to calculate the distance between two points
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum DistanceModel {
+    // Great circle on the sphere
+    Spherical,
+    // Geodesic on the WGS-84 ellipsoid (Vincenty's formula)
+    Wgs84
+}
+impl DistanceModel {
+    pub const NAMES: [&'static str; 2] = ["spherical", "wgs84"];
+
+    pub fn from_name(name: &str) -> Option<DistanceModel> {
+        match name {
+            "spherical" => Some(DistanceModel::Spherical),
+            "wgs84" => Some(DistanceModel::Wgs84),
+            _ => None
+        }
+    }
+
+    // Distance in mile between the points given in degrees
+    pub fn distance(&self, lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+        match self {
+            DistanceModel::Spherical => spherical_distance(lat1, lon1, lat2, lon2),
+            DistanceModel::Wgs84 => vincenty_distance(lat1, lon1, lat2, lon2)
+                .unwrap_or_else(|| spherical_distance(lat1, lon1, lat2, lon2))
+        }
+    }
+}
+
+fn spherical_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let lat1 = lat1 / 180.0 * PI;
+    let lat2 = lat2 / 180.0 * PI;
+    let lon1 = lon1 / 180.0 * PI;
+    let lon2 = lon2 / 180.0 * PI;
+
+    let d_lon = (lon1 - lon2).abs();
+
+    let x = lat1.sin() * lat2.sin() + lat1.cos() * lat2.cos() * d_lon.cos();
+    let y = ((lat2.cos() * d_lon.sin()).powi(2) + (lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos()).powi(2))
+        .sqrt();
+    let d_sigma = y.atan2(x);
+
+    d_sigma * RADIUS_MILE
+}
+
+// Inverse problem of Vincenty, which does not converge for nearly antipodal points (None is returned)
+fn vincenty_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> Option<f64> {
+    let b = WGS84_A * (1.0 - WGS84_F);
+    let l = (lon2 - lon1).to_radians();
+    let u1 = ((1.0 - WGS84_F) * lat1.to_radians().tan()).atan();
+    let u2 = ((1.0 - WGS84_F) * lat2.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..200 {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2) + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2)).sqrt();
+        if sin_sigma == 0.0 {
+            // Same point
+            return Some(0.0);
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+        // On the equator, cos2_alpha is 0
+        let cos_2sigma_m = if cos2_alpha != 0.0 { cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha } else { 0.0 };
+        let c = WGS84_F / 16.0 * cos2_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos2_alpha));
+
+        let prev = lambda;
+        lambda = l + (1.0 - c) * WGS84_F * sin_alpha *
+            (sigma + c * sin_sigma * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)));
+
+        if (lambda - prev).abs() < 1e-12 {
+            let u_sq = cos2_alpha * (WGS84_A * WGS84_A - b * b) / (b * b);
+            let a_coef = 1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let b_coef = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let d_sigma = b_coef * sin_sigma * (cos_2sigma_m + b_coef / 4.0 * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m) -
+                b_coef / 6.0 * cos_2sigma_m * (-3.0 + 4.0 * sin_sigma * sin_sigma) * (-3.0 + 4.0 * cos_2sigma_m * cos_2sigma_m)));
+
+            return Some(b * a_coef * (sigma - d_sigma) / METRE_PER_MILE);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn degrees(d: f64, m: f64, s: f64) -> f64 {
+        d.signum() * (d.abs() + m / 60.0 + s / 3600.0)
+    }
+
+    #[test]
+    fn vincenty() {
+        // Flinders Peak to Buninyong, the example of Vincenty's paper
+        let (lat1, lon1) = (degrees(-37.0, 57.0, 3.72030), degrees(144.0, 25.0, 29.52440));
+        let (lat2, lon2) = (degrees(-37.0, 39.0, 10.15610), degrees(143.0, 55.0, 35.38390));
+        let distance = vincenty_distance(lat1, lon1, lat2, lon2).unwrap() * METRE_PER_MILE;
+        assert!((distance - 54972.271).abs() < 0.001, "{}", distance);
+
+        assert_eq!(vincenty_distance(lat1, lon1, lat1, lon1), Some(0.0));
+        // Along the equator
+        let distance = vincenty_distance(0.0, 0.0, 0.0, 1.0).unwrap() * METRE_PER_MILE;
+        assert!((distance - 111319.491).abs() < 0.001, "{}", distance);
+    }
+
+    #[test]
+    fn nearly_antipodal() {
+        // Vincenty's formula does not converge, so the spherical distance is used
+        assert_eq!(vincenty_distance(0.0, 0.0, 0.5, 179.7), None);
+        assert_eq!(DistanceModel::Wgs84.distance(0.0, 0.0, 0.5, 179.7), DistanceModel::Spherical.distance(0.0, 0.0, 0.5, 179.7));
+    }
+
+    #[test]
+    fn spherical() {
+        // A quarter of the great circle
+        let distance = DistanceModel::Spherical.distance(0.0, 0.0, 0.0, 90.0);
+        assert!((distance - RADIUS_MILE * PI / 2.0).abs() < 1e-9);
+    }
+}
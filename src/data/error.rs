@@ -0,0 +1,56 @@
+use std::fmt;
+use std::io;
+
+pub enum DataError {
+    MissingFile { path: String, source: io::Error },
+    MalformedJson { path: String, message: String },
+    UnknownCountryCode { path: String, city: String, country: String },
+    BadCoordinates { path: String, city: String, value: String },
+    MissingRouteFile { path: String, hub: String, airport: String },
+    UnknownRegion { path: String, city: String, country: String, region: String }
+}
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataError::MissingFile { path, source } =>
+                write!(f, "{}: cannot read the file: {}", path, source),
+            DataError::MalformedJson { path, message } =>
+                write!(f, "{}: malformed JSON: {}", path, message),
+            DataError::UnknownCountryCode { path, city, country } =>
+                write!(f, "{}: city {} has an unknown country code {}", path, city, country),
+            DataError::BadCoordinates { path, city, value } =>
+                write!(f, "{}: city {} has a bad coordinate \"{}\"", path, city, value),
+            DataError::MissingRouteFile { path, hub, airport } =>
+                write!(f, "{}: route file for airport {} of hub {} is missing", path, airport, hub),
+            DataError::UnknownRegion { path, city, country, region } =>
+                write!(f, "{}: cannot determine the area for city {} in {} (region \"{}\")", path, city, country, region)
+        }
+    }
+}
+impl fmt::Debug for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+impl std::error::Error for DataError {}
+
+// Error in finding an airport by its code or the code of its city
+pub enum LookupError {
+    UnknownCity(String),
+    AmbiguousCity(String, Vec<String>)
+}
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LookupError::UnknownCity(code) => write!(f, "Unknown city: {}", code),
+            LookupError::AmbiguousCity(code, airports) =>
+                write!(f, "{} has more than one airport, specify one of: {}", code, airports.join(", "))
+        }
+    }
+}
+impl fmt::Debug for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+impl std::error::Error for LookupError {}
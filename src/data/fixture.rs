@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::Path;
+use super::{AreaCode, City, CityData, Route};
+use super::distance::DistanceModel;
+use super::region;
+use super::tpm::TpmTable;
+
+// Airports of the test world: code, city, area, latitude and longitude
+const AIRPORTS: [(&str, &str, AreaCode, f64, f64); 16] = [
+    ("HND", "TYO", AreaCode::Japan, 35.55, 139.78),
+    ("NRT", "TYO", AreaCode::Japan, 35.76, 140.39),
+    ("HKG", "HKG", AreaCode::Asia, 22.31, 113.91),
+    ("BKK", "BKK", AreaCode::Asia, 13.69, 100.75),
+    ("DEL", "DEL", AreaCode::Asia, 28.56, 77.10),
+    ("LHR", "LON", AreaCode::EuropeMiddleEast, 51.47, -0.45),
+    ("MAD", "MAD", AreaCode::EuropeMiddleEast, 40.47, -3.56),
+    ("DOH", "DOH", AreaCode::EuropeMiddleEast, 25.27, 51.61),
+    ("JNB", "JNB", AreaCode::Africa, -26.14, 28.24),
+    ("SYD", "SYD", AreaCode::Oceania, -33.95, 151.18),
+    ("JFK", "NYC", AreaCode::NorthAmerica, 40.64, -73.78),
+    ("LAX", "LAX", AreaCode::NorthAmerica, 33.94, -118.41),
+    ("DFW", "DFW", AreaCode::NorthAmerica, 32.90, -97.04),
+    ("HNL", "HNL", AreaCode::NorthAmerica, 21.32, -157.92),
+    ("OGG", "OGG", AreaCode::NorthAmerica, 20.90, -156.43),
+    ("GRU", "SAO", AreaCode::SouthAmerica, -23.43, -46.47)
+];
+
+// Routes of the test world, flown in both directions
+const ROUTES: [(&str, &str); 58] = [
+    // Asia
+    ("HND", "HKG"), ("NRT", "HKG"), ("HND", "BKK"), ("NRT", "BKK"), ("HKG", "BKK"), ("HKG", "DEL"), ("BKK", "DEL"),
+    // Asia - Europe/Middle East
+    ("HKG", "LHR"), ("DEL", "LHR"), ("BKK", "DOH"), ("HKG", "DOH"), ("DEL", "DOH"), ("NRT", "LHR"),
+    // Europe/Middle East and Africa
+    ("DOH", "LHR"), ("LHR", "MAD"), ("DOH", "MAD"), ("DOH", "JNB"), ("JNB", "LHR"),
+    // Atlantic
+    ("LHR", "JFK"), ("MAD", "JFK"), ("DOH", "JFK"), ("DOH", "DFW"), ("LHR", "DFW"), ("MAD", "GRU"), ("JNB", "GRU"), ("DEL", "JFK"),
+    // Americas
+    ("GRU", "JFK"), ("GRU", "DFW"), ("JFK", "LAX"), ("JFK", "DFW"), ("DFW", "LAX"),
+    ("LAX", "HNL"), ("DFW", "HNL"), ("JFK", "HNL"), ("HNL", "OGG"), ("LAX", "OGG"),
+    // Pacific
+    ("LAX", "HND"), ("LAX", "NRT"), ("DFW", "NRT"), ("JFK", "HND"), ("HNL", "NRT"), ("HNL", "HND"),
+    ("LAX", "HKG"), ("DFW", "HKG"), ("LAX", "SYD"), ("DFW", "SYD"), ("HNL", "SYD"),
+    // Oceania
+    ("SYD", "HKG"), ("SYD", "NRT"), ("SYD", "BKK"), ("SYD", "JNB"), ("SYD", "DOH"),
+    // Others
+    ("OGG", "DFW"), ("BKK", "LHR"), ("MAD", "DFW"), ("GRU", "LAX"), ("JNB", "BKK"), ("DEL", "HND")
+];
+
+// Test world with the default regions, where the points of a route are its distance in mile
+pub fn world() -> CityData {
+    let mut cities: Vec<City> = AIRPORTS.iter().enumerate().map(|(index, (code, metro, area, lat, lon))| City {
+        area: *area,
+        lon: *lon,
+        lat: *lat,
+        code: String::from(*code),
+        metro: String::from(*metro),
+        country: String::new(),
+        hub: true,
+        routes: Vec::new(),
+        index
+    }).collect();
+
+    let index = |code: &str| AIRPORTS.iter().position(|a| a.0 == code).unwrap();
+    for (a, b) in ROUTES.iter() {
+        for (from, to) in &[(index(a), index(b)), (index(b), index(a))] {
+            let points = City::calc_distance(&cities[*from], &cities[*to], DistanceModel::Spherical) as u32;
+            cities[*from].routes.push(Route { to: *to, points, airlines: Vec::new() });
+        }
+    }
+    for city in &mut cities {
+        city.routes.sort_by_key(|r| r.to);
+    }
+
+    let regions = region::parse_regions(include_str!("../../data/regions.json"), "regions.json", &cities).unwrap();
+    CityData { cities, regions, tpm: TpmTable::default(), unknown_airlines: 0 }
+}
+
+// Changes the points of the route between the airports, which is kept even if it earns nothing
+pub fn set_points(city_data: &mut CityData, from: &str, to: &str, points: u32) {
+    let to = city_data.find_airport(to).unwrap().index;
+    let from = city_data.find_airport(from).unwrap().index;
+    city_data.cities[from].routes.iter_mut().find(|r| r.to == to).unwrap().points = points;
+}
+
+// Indices of the airports of the codes
+pub fn route(city_data: &CityData, codes: &[&str]) -> Vec<usize> {
+    codes.iter().map(|c| city_data.find_airport(c).unwrap().index).collect()
+}
+
+// Data directory in the format of the downloaded dataset, removed at the end of the test
+pub struct DataDir {
+    pub path: String
+}
+impl DataDir {
+    // Empty directory (with the directory of the route files) unique to the test
+    pub fn new(name: &str) -> DataDir {
+        let path = std::env::temp_dir().join(format!("owe-explorer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("routes")).unwrap();
+
+        DataDir { path: path.to_string_lossy().into_owned() }
+    }
+
+    pub fn file(&self, name: &str) -> String {
+        Path::new(&self.path).join(name).to_string_lossy().into_owned()
+    }
+
+    pub fn write(&self, name: &str, contents: &str) {
+        fs::write(self.file(name), contents).unwrap();
+    }
+}
+impl Drop for DataDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+const SAMPLE_COUNTRIES: &str = r#"[
+    {"name": "Japan", "alpha-2": "JP", "region": "Asia", "sub-region": "Eastern Asia", "intermediate-region": ""},
+    {"name": "Hong Kong", "alpha-2": "HK", "region": "Asia", "sub-region": "Eastern Asia", "intermediate-region": ""},
+    {"name": "United Kingdom", "alpha-2": "GB", "region": "Europe", "sub-region": "Northern Europe", "intermediate-region": ""},
+    {"name": "United States of America", "alpha-2": "US", "region": "Americas", "sub-region": "Northern America", "intermediate-region": ""},
+    {"name": "Australia", "alpha-2": "AU", "region": "Oceania", "sub-region": "Australia and New Zealand", "intermediate-region": ""}
+]"#;
+
+// MEL is not reached from any hub, so it is not in the dataset
+const SAMPLE_MAP_DATA: &str = r#"{"mapData": {"cities": {"city": [
+    {"cityCode": "TYO", "countryCode": "JP", "lat": "35.68", "lon": "139.76", "timeZone": "9", "name": "Tokyo"},
+    {"cityCode": "LON", "countryCode": "GB", "lat": "51.5", "lon": "-0.12", "timeZone": "0", "name": "London"},
+    {"cityCode": "HKG", "countryCode": "HK", "lat": "22.3", "lon": "114.17", "timeZone": "8", "name": "Hong Kong"},
+    {"cityCode": "DFW", "countryCode": "US", "lat": "32.78", "lon": "-96.8", "timeZone": "-6", "name": "Dallas"},
+    {"cityCode": "SYD", "countryCode": "AU", "lat": "-33.87", "lon": "151.21", "timeZone": "10", "name": "Sydney"},
+    {"cityCode": "MEL", "countryCode": "AU", "lat": "-37.81", "lon": "144.96", "timeZone": "10", "name": "Melbourne"}
+]}}}"#;
+
+const SAMPLE_HUBS: &str = r#"[
+    {"city_code": "TYO", "airports": ["HND", "NRT"]},
+    {"city_code": "LON", "airports": ["LHR"]},
+    {"city_code": "HKG", "airports": ["HKG"]}
+]"#;
+
+// Route files of the hubs. The coordinates of NRT are not given, and XYZ is not in the map data.
+const SAMPLE_ROUTE_FILES: [(&str, &str); 4] = [
+    ("hnd", r#"[
+        {"iata": "HKG", "lat": 22.31, "lon": 113.91, "airlines": ["JL", "CX"]},
+        {"iata": "LHR", "lat": 51.47, "lon": -0.45, "airlines": ["JL", "BA"]},
+        {"iata": "DFW", "lat": 32.9, "lon": -97.04, "airlines": ["AA", "JL"]}
+    ]"#),
+    ("nrt", r#"[
+        {"iata": "SYD", "lat": -33.95, "lon": 151.18, "airlines": ["QF", "JL"]},
+        {"iata": "DFW", "airlines": ["UA"]},
+        {"iata": "LHR", "airlines": ["BA"]}
+    ]"#),
+    ("lhr", r#"[
+        {"iata": "HND", "lat": 35.55, "lon": 139.78, "airlines": ["BA", "JL"]},
+        {"iata": "HKG", "airlines": ["CX", "BA"]},
+        {"iata": "DFW", "airlines": ["AA", "BA"]},
+        {"iata": "XYZ", "airlines": ["BA"]}
+    ]"#),
+    ("hkg", r#"[
+        {"iata": "HND", "airlines": ["CX"]},
+        {"iata": "LHR"},
+        {"iata": "SYD", "airlines": ["CX", "QF"]}
+    ]"#)
+];
+
+// Small dataset with the default rules, regions and carriers
+pub fn data_dir(name: &str) -> DataDir {
+    let dir = DataDir::new(name);
+    dir.write("country-code.json", SAMPLE_COUNTRIES);
+    dir.write("owe-map-data.json", SAMPLE_MAP_DATA);
+    dir.write("hub-info.json", SAMPLE_HUBS);
+    for (airport, routes) in &SAMPLE_ROUTE_FILES {
+        dir.write(&format!("routes/{}.json", airport), routes);
+    }
+
+    dir
+}
+
+// Cities and their routes in a comparable form
+pub fn summary(cities: &[City]) -> Vec<String> {
+    cities.iter().map(|c| {
+        let routes: Vec<String> = c.routes.iter().map(|r| format!("{}{:?}", cities[r.to].code, r.airlines)).collect();
+        format!("{} {} {} {:?} {} {} {} {}", c.code, c.metro, c.country, c.area, c.lat, c.lon, c.hub, routes.join(" "))
+    }).collect()
+}
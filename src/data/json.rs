@@ -1,44 +1,57 @@
-use serde;
 use serde::{Deserialize};
 use serde_json::{Value};
 use std::fs::File;
 use std::io::prelude::*;
+use super::error::DataError;
 
+// Route departing from the airport of the file
 #[derive(Deserialize)]
 pub struct JsonFr24Route {
     pub iata: Option<String>,
-    pub icao: Option<String>
+    // Coordinates of the airport (optional)
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    // Codes of the operating airlines (optional)
+    #[serde(default)]
+    pub airlines: Vec<String>
 }
 
 pub struct JsonCountryCode {
-    pub name: String,
     pub code: String,
     pub region: String,
     pub intermediate_region: String,
     pub sub_region: String
 }
 impl JsonCountryCode {
-    pub fn load_from_file(filename: &str) -> Vec<JsonCountryCode> {
-        let mut file = File::open(filename).unwrap();
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-
-        let j: Value = serde_json::from_str(&contents).unwrap();
+    pub fn load_from_file(filename: &str) -> Result<Vec<JsonCountryCode>, DataError> {
+        let j: Value = load_from_json_file(filename)?;
         let mut v = Vec::new();
 
+        let field = |c: &Value, key: &str| match c[key].as_str() {
+            Some(s) => Ok(String::from(s)),
+            None => Err(DataError::MalformedJson {
+                path: String::from(filename),
+                message: format!("missing \"{}\" in {}", key, c)
+            })
+        };
+
         if let Value::Array(countries) = j {
             for c in countries {
                 v.push(JsonCountryCode {
-                    name: String::from(c["name"].as_str().unwrap()),
-                    code: String::from(c["alpha-2"].as_str().unwrap()),
-                    region: String::from(c["region"].as_str().unwrap()),
-                    intermediate_region: String::from(c["intermediate-region"].as_str().unwrap()),
-                    sub_region: String::from(c["sub-region"].as_str().unwrap())
+                    code: field(&c, "alpha-2")?,
+                    region: field(&c, "region")?,
+                    intermediate_region: field(&c, "intermediate-region")?,
+                    sub_region: field(&c, "sub-region")?
                 });
             }
+        } else {
+            return Err(DataError::MalformedJson {
+                path: String::from(filename),
+                message: String::from("the top level is not an array")
+            });
         }
 
-        v
+        Ok(v)
     }
 }
 
@@ -49,8 +62,7 @@ pub struct JsonCityData {
     pub countryCode: String,
     pub lat: String,
     pub lon: String,
-    pub timeZone: String,
-    pub name: String
+    pub timeZone: String
 }
 #[derive(Deserialize)]
 pub struct JsonCities {
@@ -74,10 +86,14 @@ pub struct JsonHubInfo {
     pub airports: Vec<String>
 }
 
-pub fn load_from_json_file<T: for<'a> Deserialize<'a>>(filename: &str) -> T {
-    let mut file = File::open(filename).unwrap();
-    let mut contents: String = String::new();
-    file.read_to_string(&mut contents).unwrap();
+pub fn load_from_json_file<T: for<'a> Deserialize<'a>>(filename: &str) -> Result<T, DataError> {
+    let read = || -> std::io::Result<String> {
+        let mut file = File::open(filename)?;
+        let mut contents: String = String::new();
+        file.read_to_string(&mut contents)?;
+        Ok(contents)
+    };
+    let contents = read().map_err(|source| DataError::MissingFile { path: String::from(filename), source })?;
 
-    serde_json::from_str(&contents).unwrap()
+    serde_json::from_str(&contents).map_err(|e| DataError::MalformedJson { path: String::from(filename), message: e.to_string() })
 }
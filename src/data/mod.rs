@@ -1,15 +1,32 @@
+mod area;
+mod cache;
+mod carrier;
+pub mod check;
+pub mod class;
+pub mod distance;
+pub mod error;
+#[cfg(test)]
+pub mod fixture;
 mod json;
+pub mod program;
+pub mod region;
+pub mod tpm;
 
 use std::path::Path;
-use std::collections::{HashMap, HashSet};
-use std::f64::consts::PI;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
 
-// Radius of the earth in mile
-const RADIUS_MILE: f64 = 3958.756;
-// Multiplier to calculate frequent flyer point
-const CLASS_MUL: f64 = 1.25;
+use area::AreaRules;
+use class::BookingClass;
+use distance::DistanceModel;
+use error::{DataError, LookupError};
+use program::EarningProgram;
 
-#[derive(PartialEq)]
+// Compiled dataset in the data directory
+const CACHE_FILE: &str = "owe-cache.bin";
+
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum AreaCode {
     Japan,
     Asia,
@@ -19,208 +36,342 @@ pub enum AreaCode {
     NorthAmerica,
     SouthAmerica
 }
+impl AreaCode {
+    pub fn continent(&self) -> Continent {
+        match self {
+            AreaCode::Japan | AreaCode::Asia => Continent::Asia,
+            AreaCode::EuropeMiddleEast => Continent::EuropeMiddleEast,
+            AreaCode::Oceania => Continent::Oceania,
+            AreaCode::Africa => Continent::Africa,
+            AreaCode::NorthAmerica => Continent::NorthAmerica,
+            AreaCode::SouthAmerica => Continent::SouthAmerica
+        }
+    }
+}
+
+// Continents as defined by the oneworld Explorer fare (Japan belongs to Asia)
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize)]
+pub enum Continent {
+    Asia,
+    EuropeMiddleEast,
+    Oceania,
+    Africa,
+    NorthAmerica,
+    SouthAmerica
+}
+impl Continent {
+    pub const ALL: [Continent; 6] = [
+        Continent::Asia, Continent::EuropeMiddleEast, Continent::Oceania,
+        Continent::Africa, Continent::NorthAmerica, Continent::SouthAmerica
+    ];
+    // Names accepted on the command line, in the same order as ALL
+    pub const NAMES: [&'static str; 6] = ["asia", "europe", "oceania", "africa", "na", "sa"];
+
+    pub fn from_name(name: &str) -> Option<Continent> {
+        Continent::NAMES.iter().position(|n| *n == name).map(|i| Continent::ALL[i])
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Continent::Asia => "Asia",
+            Continent::EuropeMiddleEast => "Europe/Middle East",
+            Continent::Oceania => "South West Pacific",
+            Continent::Africa => "Africa",
+            Continent::NorthAmerica => "North America",
+            Continent::SouthAmerica => "South America"
+        }
+    }
+}
+
+// Route from a city to another city
+#[derive(Clone)]
+pub struct Route {
+    pub to: usize,
+    pub points: u32,
+    // Codes of the operating airlines, empty if unknown
+    pub airlines: Vec<String>
+}
 
 pub struct City {
     pub area: AreaCode,
     lon: f64,
     lat: f64,
+    // Code of the airport
     pub code: String,
+    // Code of the city to which the airport belongs (same as the airport for most cities)
+    pub metro: String,
     pub country: String,
     pub hub: bool,
-    distances: Vec<u32>,
+    // Routes departing from the city, sorted by the index of the destination
+    routes: Vec<Route>,
     pub index: usize
 }
 impl City {
-    fn from_json_data(city: &json::JsonCityData, is_hub: bool, country_map: &HashMap<String, &json::JsonCountryCode>, index: usize) -> City {
-        let area = CityData::find_area(&city.countryCode, &city.timeZone, country_map);
+    fn from_json_data(city: &json::JsonCityData, airport: &str, area: AreaCode, is_hub: bool, coordinates: Option<&(f64, f64)>,
+        index: usize, path: &str) -> Result<City, DataError> {
+        let parse_coordinate = |value: &str| value.parse::<f64>().map_err(|_| DataError::BadCoordinates {
+            path: String::from(path), city: city.cityCode.clone(), value: String::from(value)
+        });
 
-        City {
+        // Coordinates of the city are used unless those of the airport are known
+        let (lat, lon) = match coordinates {
+            Some(c) => *c,
+            None => (parse_coordinate(&city.lat)?, parse_coordinate(&city.lon)?)
+        };
+
+        Ok(City {
             area,
-            lon: city.lon.parse::<f64>().unwrap(),
-            lat: city.lat.parse::<f64>().unwrap(),
-            code: city.cityCode.clone(),
+            lon,
+            lat,
+            code: String::from(airport),
+            metro: city.cityCode.clone(),
             country: city.countryCode.clone(),
             hub: is_hub,
-            distances: Vec::new(),
+            routes: Vec::new(),
             index
-        }
+        })
     }
-    fn calc_distance(from: &City, to: &City) -> f64 {
-        let lat1 = from.lat / 180.0 * PI;
-        let lat2 = to.lat / 180.0 * PI;
-        let lon1 = from.lon / 180.0 * PI;
-        let lon2 = to.lon / 180.0 * PI;
-    
-        let d_lon = (lon1 - lon2).abs();
-    
-        let x = lat1.sin() * lat2.sin() + lat1.cos() * lat2.cos() * d_lon.cos();
-        let y = ((lat2.cos() * d_lon.sin()).powi(2) + (lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos()).powi(2))
-            .sqrt();
-        let d_sigma = y.atan2(x);
-
-        d_sigma * RADIUS_MILE
+    pub fn calc_distance(from: &City, to: &City, model: DistanceModel) -> f64 {
+        model.distance(from.lat, from.lon, to.lat, to.lon)
     }
-    pub fn calc_point(from: &City, to: &City) -> u32 {
-        let mul = if from.area == AreaCode::Japan && from.area == to.area {
-            // Japan domestic
-            2.0
-        } else if (from.area == AreaCode::Japan && (to.area == AreaCode::Asia || to.area == AreaCode::Oceania)) ||
-            (to.area == AreaCode::Japan && (from.area == AreaCode::Asia || from.area == AreaCode::Oceania)) {
-            if from.country == "RU" || to.country == "RU" {
-                // Russia (East of Ural) is considered as Europe
-                1.0
-            } else {
-                1.5
-            }
-        } else {
-            1.0
-        };
-
-        let extra = if from.area == AreaCode::Japan || to.area == AreaCode::Japan {
-            400.0
-        } else {
-            0.0
-        };
-
-        (City::calc_distance(from, to) * mul * CLASS_MUL + extra).floor() as u32
+    pub fn lat(&self) -> f64 {
+        self.lat
     }
-
+    pub fn lon(&self) -> f64 {
+        self.lon
+    }
+    pub fn route(&self, to: &City) -> Option<&Route> {
+        self.routes.binary_search_by_key(&to.index, |r| r.to).ok().map(|i| &self.routes[i])
+    }
+    // Points of the route to the city, or 0 if there is no route
     pub fn distance(&self, to: &City) -> u32 {
-        self.distances[to.index]
+        self.route(to).map(|r| r.points).unwrap_or(0)
+    }
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
     }
 }
 
 pub struct CityData {
     pub cities: Vec<City>,
+    // Regions restricting the routes visiting them
+    pub regions: Vec<region::Region>,
+    // Ticketed point mileages credited instead of the calculated distances
+    pub tpm: tpm::TpmTable,
+    // Number of the routes whose airlines are unknown, which cannot be checked against the eligible carriers
+    pub unknown_airlines: usize
 }
 impl CityData {
-    fn find_area(country_code: &str, time_zone: &str, map: &HashMap<String, &json::JsonCountryCode>) -> AreaCode {
-        let country = map[country_code];
-
-        if country.code == "JP" {
-            AreaCode::Japan
-        } else if country.region == "Asia" {
-            if country.sub_region == "Western Asia" {
-                AreaCode::EuropeMiddleEast
-            } else if country.code == "IR" { // Iran is in Middle-East
-                AreaCode::EuropeMiddleEast
-            } else {
-                AreaCode::Asia
-            }
-        } else if country.region == "Oceania" {
-            AreaCode::Oceania
-        } else if country.region == "Europe" {
-            if country.code == "RU" {
-                match time_zone {
-                    "6" | "7" | "8" | "9" | "10" | "11" => AreaCode::Asia,
-                    _ => AreaCode::EuropeMiddleEast
+    // Finds the airport by its code, or by the code of its city if the city has only one airport
+    pub fn find_airport(&self, code: &str) -> Result<&City, LookupError> {
+        if let Some(city) = self.cities.iter().find(|c| c.code == code) {
+            return Ok(city);
+        }
+
+        let airports: Vec<&City> = self.cities.iter().filter(|c| c.metro == code).collect();
+        match airports.len() {
+            0 => Err(LookupError::UnknownCity(String::from(code))),
+            1 => Ok(airports[0]),
+            _ => Err(LookupError::AmbiguousCity(String::from(code), airports.iter().map(|c| c.code.clone()).collect()))
+        }
+    }
+
+    pub fn load_all_data(data_dir: &str, program: &dyn EarningProgram, class: &BookingClass, use_cache: bool) -> Result<CityData, DataError> {
+        let cache_path = Path::new(data_dir).join(CACHE_FILE).to_string_lossy().into_owned();
+
+        let rules = AreaRules::load(data_dir)?;
+
+        let cached = if use_cache { cache::load(data_dir, &cache_path, rules.hash) } else { None };
+        let mut cities = match cached {
+            Some(cities) => cities,
+            None => {
+                let (cities, sources) = CityData::load_sources(data_dir, &rules)?;
+                if use_cache {
+                    cache::save(data_dir, &cache_path, rules.hash, &sources, &cities);
                 }
-            } else {
-                AreaCode::EuropeMiddleEast
+                cities
             }
-        } else if country.region == "Africa" {
-            if country.code == "DZ" || country.code == "MA" { // Algeria and Morocco are in Europe
-                AreaCode::EuropeMiddleEast
-            } else if country.code == "EG" || country.code == "LY" || country.code == "SD" { // Egypt, Lybia and Sudan are in Middle East
-                AreaCode::EuropeMiddleEast
-            } else {
-                AreaCode::Africa
-            }
-        } else if country.region == "Americas" {
-            if country.sub_region == "Northern America" {
-                AreaCode::NorthAmerica
-            } else {
-                if country.intermediate_region == "Caribbean" || country.intermediate_region == "Central America" {
-                    AreaCode::NorthAmerica
-                } else {
-                    AreaCode::SouthAmerica
-                }
+        };
+
+        let carriers = carrier::load_carriers(data_dir)?;
+        let unknown_airlines = carrier::exclude_routes(&mut cities, &carriers);
+
+        // Calculate points
+        let tpm = tpm::TpmTable::load(data_dir)?;
+        for i in 0..cities.len() {
+            for k in 0..cities[i].routes.len() {
+                let to = &cities[cities[i].routes[k].to];
+                let c = program.calc_point(&cities[i], to, class, tpm.find(&cities[i], to));
+                cities[i].routes[k].points = c;
             }
-        } else {
-            panic!("Cannot determine region for {}", country.code);
         }
+
+        let regions = region::load_regions(data_dir, &cities)?;
+
+        Ok(CityData {
+            cities,
+            regions,
+            tpm,
+            unknown_airlines
+        })
     }
 
-    pub fn load_all_data(data_dir: &str) -> CityData {
+    // Loads the cities and the routes between them (without points) from the JSON files,
+    // also returning the paths of the files read (relative to the data directory)
+    fn load_sources(data_dir: &str, rules: &AreaRules) -> Result<(Vec<City>, Vec<String>), DataError> {
+        let path = |name: &str| Path::new(data_dir).join(name).to_string_lossy().into_owned();
+        let map_data_path = path("owe-map-data.json");
+        let mut sources = vec![String::from("country-code.json"), String::from("owe-map-data.json"), String::from("hub-info.json")];
+
         // Load JSON files
-        let country_code = json::JsonCountryCode::load_from_file(Path::new(data_dir).join("country-code.json").to_str().unwrap());
-        let map_data: json::JsonMapData = json::load_from_json_file(Path::new(data_dir).join("owe-map-data.json").to_str().unwrap());
-        let hubs: Vec<json::JsonHubInfo> = json::load_from_json_file(Path::new(data_dir).join("hub-info.json").to_str().unwrap());
+        let country_code = json::JsonCountryCode::load_from_file(&path("country-code.json"))?;
+        let map_data: json::JsonMapData = json::load_from_json_file(&map_data_path)?;
+        let hubs: Vec<json::JsonHubInfo> = json::load_from_json_file(&path("hub-info.json"))?;
 
-        // Composite all the map data
+        // Routes departing from each airport of the hubs, and the destination airports
+        let mut hub_routes = Vec::new();
         let mut airports_set = HashSet::new();
         let mut hubs_map = HashMap::new();
         let mut airport_to_city_map = HashMap::new();
+        // Coordinates of the airports given in the route files
+        let mut coordinates = HashMap::new();
 
         for hub in hubs {
-            let mut reachable_airport_set = HashSet::new();
-            for airport in hub.airports {
-                let route: Vec<json::JsonFr24Route> = 
-                    json::load_from_json_file(Path::new(data_dir).join("routes").join({
-                        let mut s = airport.to_lowercase(); s.push_str(".json"); s
-                    }).to_str().unwrap());
-                
+            for airport in &hub.airports {
+                let route_name = format!("routes/{}.json", airport.to_lowercase());
+                let route_path = path(&route_name);
+                let route: Vec<json::JsonFr24Route> = match json::load_from_json_file(&route_path) {
+                    Ok(route) => route,
+                    Err(DataError::MissingFile { path, .. }) => return Err(DataError::MissingRouteFile {
+                        path, hub: hub.city_code.clone(), airport: airport.clone()
+                    }),
+                    Err(e) => return Err(e)
+                };
+                sources.push(route_name);
+
                 airport_to_city_map.insert(airport.clone(), hub.city_code.clone());
 
-                for r in route {
-                    if let Some(ap) = r.iata {
+                for r in &route {
+                    if let Some(ap) = &r.iata {
                         airports_set.insert(ap.clone());
-                        reachable_airport_set.insert(ap.clone());
+                        if let (Some(lat), Some(lon)) = (r.lat, r.lon) {
+                            coordinates.insert(ap.clone(), (lat, lon));
+                        }
                     }
                 }
+                hub_routes.push((airport.clone(), route));
             }
-            hubs_map.insert(hub.city_code, reachable_airport_set);
+            hubs_map.insert(hub.city_code, hub.airports);
         }
 
         let mut country_map = HashMap::new();
-        for i in 0..country_code.len() {
-            country_map.insert(String::from(&country_code[i].code), &country_code[i]);
+        for country in &country_code {
+            country_map.insert(String::from(&country.code), country);
         }
 
+        // Each airport of a hub is a separate node grouped under the code of the city,
+        // while the other cities are reached at the airport with the same code as the city
         let mut cities = Vec::new();
-        let mut index = 0usize;
         for city in map_data.mapData.cities.city {
+            let airports = match hubs_map.get(&city.cityCode) {
+                Some(airports) => airports.clone(),
+                None if airports_set.contains(&city.cityCode) && !airport_to_city_map.contains_key(&city.cityCode) =>
+                    vec![city.cityCode.clone()],
+                None => continue
+            };
             let is_hub = hubs_map.contains_key(&city.cityCode);
-            let is_reachable_from_hub = airports_set.contains(&city.cityCode);
-
-            if is_hub || is_reachable_from_hub {
-                let city_data = City::from_json_data(&city, is_hub, &country_map, index);
+            let area = rules.find_area(&city, &country_map, &map_data_path)?;
 
-                cities.push(city_data);
-                index += 1;
+            for airport in airports {
+                let index = cities.len();
+                cities.push(City::from_json_data(&city, &airport, area, is_hub, coordinates.get(&airport), index, &map_data_path)?);
             }
         }
-        // Calculate distance
-        for i in 0..cities.len() {
-            for j in 0..cities.len() {
-                // Is there any route?
-                let reachable =
-                    if cities[i].hub {
-                        let reachable_set = &hubs_map[&cities[i].code];
-                        if airport_to_city_map.contains_key(&cities[j].code) {
-                            let city_code = &airport_to_city_map[&cities[j].code];
-                            reachable_set.contains(city_code)
-                        } else {
-                            reachable_set.contains(&cities[j].code)
+        let index_map: HashMap<String, usize> = cities.iter().map(|c| (c.code.clone(), c.index)).collect();
+
+        // Find the routes. The routes of a hub are those in its route files, while a route from another city
+        // to a hub is assumed to exist if the hub has a route to the city (there is no route file for the city)
+        let mut routes: BTreeMap<(usize, usize), Vec<String>> = BTreeMap::new();
+        for (airport, route) in hub_routes {
+            let from = match index_map.get(&airport) {
+                Some(from) => *from,
+                None => continue
+            };
+            for r in route {
+                let to = match r.iata.as_ref().and_then(|ap| index_map.get(ap)) {
+                    Some(to) if *to != from => *to,
+                    _ => continue
+                };
+
+                let mut add = |key| {
+                    let airlines = routes.entry(key).or_default();
+                    for airline in &r.airlines {
+                        if !airlines.contains(airline) {
+                            airlines.push(airline.clone());
                         }
-                    } else if cities[j].hub {
-                        let reachable_set = &hubs_map[&cities[j].code];
-                        reachable_set.contains(&cities[i].code)
-                    } else {
-                        false
-                    };
-
-                if reachable {
-                    let c = City::calc_point(&cities[i], &cities[j]);
-                    cities[i].distances.push(c);
-                } else {
-                    cities[i].distances.push(0);
+                    }
+                };
+                add((from, to));
+                if !cities[to].hub {
+                    add((to, from));
                 }
             }
         }
+        for ((from, to), mut airlines) in routes {
+            airlines.sort();
+            cities[from].routes.push(Route { to, points: 0, airlines });
+        }
 
-        CityData {
-            cities,
+        Ok((cities, sources))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_sources() {
+        let dir = fixture::data_dir("load-sources");
+        let rules = AreaRules::load(&dir.path).unwrap();
+        let (cities, sources) = CityData::load_sources(&dir.path, &rules).unwrap();
+
+        // Airports of a hub are grouped under its city, while MEL without any route is not loaded
+        let airports: Vec<(&str, &str, AreaCode, bool)> = cities.iter().map(|c| (c.code.as_str(), c.metro.as_str(), c.area, c.hub)).collect();
+        assert_eq!(airports, vec![
+            ("HND", "TYO", AreaCode::Japan, true), ("NRT", "TYO", AreaCode::Japan, true),
+            ("LHR", "LON", AreaCode::EuropeMiddleEast, true), ("HKG", "HKG", AreaCode::Asia, true),
+            ("DFW", "DFW", AreaCode::NorthAmerica, false), ("SYD", "SYD", AreaCode::Oceania, false)
+        ]);
+        assert!(cities.iter().enumerate().all(|(i, c)| c.index == i));
+
+        // Coordinates of the airports given in the route files, or those of the city otherwise
+        let coordinates = |code: &str| cities.iter().find(|c| c.code == code).map(|c| (c.lat, c.lon)).unwrap();
+        assert_eq!(coordinates("HND"), (35.55, 139.78));
+        assert_eq!(coordinates("NRT"), (35.68, 139.76));
+        assert_eq!(coordinates("DFW"), (32.9, -97.04));
+
+        // Routes to the cities other than the hubs are flown in both directions, while XYZ is not in the map data
+        let routes: Vec<String> = cities.iter().map(|c| {
+            let to: Vec<&str> = c.routes.iter().map(|r| cities[r.to].code.as_str()).collect();
+            format!("{} {}", c.code, to.join(" "))
+        }).collect();
+        assert_eq!(routes, vec!["HND LHR HKG DFW", "NRT LHR DFW SYD", "LHR HND HKG DFW", "HKG HND LHR SYD", "DFW HND NRT LHR", "SYD NRT HKG"]);
+        let dfw = &cities[4];
+        assert_eq!(dfw.routes.iter().map(|r| r.airlines.join(",")).collect::<Vec<_>>(), vec!["AA,JL", "UA", "AA,BA"]);
+
+        assert_eq!(sources, vec!["country-code.json", "owe-map-data.json", "hub-info.json",
+            "routes/hnd.json", "routes/nrt.json", "routes/lhr.json", "routes/hkg.json"]);
+    }
+
+    #[test]
+    fn missing_route_file() {
+        let dir = fixture::data_dir("missing-route-file");
+        std::fs::remove_file(dir.file("routes/nrt.json")).unwrap();
+        let rules = AreaRules::load(&dir.path).unwrap();
+
+        match CityData::load_sources(&dir.path, &rules) {
+            Err(DataError::MissingRouteFile { hub, airport, .. }) => assert_eq!((hub.as_str(), airport.as_str()), ("TYO", "NRT")),
+            _ => panic!("the missing route file is not reported")
         }
     }
 }
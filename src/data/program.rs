@@ -0,0 +1,284 @@
+use serde::Serialize;
+use super::{AreaCode, City};
+use super::class::{BookingClass, lookup};
+use super::distance::DistanceModel;
+
+// Where the distance of a segment comes from
+#[derive(PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistanceSource {
+    // Ticketed point mileage in the TPM table
+    Tpm,
+    // Calculated from the coordinates of the airports
+    Computed
+}
+
+// Breakdown of the points earned on a segment
+pub struct Earning {
+    // Distance in mile
+    pub distance: u32,
+    pub distance_source: DistanceSource,
+    // Multiplier applied to the distance (None for programs earning by distance band)
+    pub multiplier: Option<f64>,
+    pub bonus: u32,
+    pub points: u32
+}
+
+// Frequent flyer program to which the flights are credited
+pub trait EarningProgram {
+    fn name(&self) -> &'static str;
+    fn earn(&self, from: &City, to: &City, class: &BookingClass, tpm: Option<u32>) -> Earning;
+    // Model of the earth with which the program measures the flight distance
+    fn distance_model(&self) -> DistanceModel;
+
+    // Distance of the segment, which is the TPM if known or calculated with the distance model otherwise
+    fn distance(&self, from: &City, to: &City, tpm: Option<u32>) -> (f64, DistanceSource) {
+        match tpm {
+            Some(tpm) => (tpm as f64, DistanceSource::Tpm),
+            None => (City::calc_distance(from, to, self.distance_model()), DistanceSource::Computed)
+        }
+    }
+
+    fn calc_point(&self, from: &City, to: &City, class: &BookingClass, tpm: Option<u32>) -> u32 {
+        self.earn(from, to, class, tpm).points
+    }
+}
+
+// Class multipliers of JAL Mileage Bank for the oneworld partner flights
+const JAL_CLASS_MUL: [(&str, f64); 7] = [
+    ("FAP", 1.5), ("JCDIR", 1.25), ("WE", 1.0), ("YBH", 1.0),
+    ("KML", 0.7), ("VSN", 0.5), ("QOGT", 0.3)
+];
+
+// JAL Mileage Bank FLY ON points
+pub struct JalFlyOnPoints {
+    pub distance_model: DistanceModel
+}
+impl EarningProgram for JalFlyOnPoints {
+    fn name(&self) -> &'static str {
+        "JAL FLY ON points"
+    }
+    fn distance_model(&self) -> DistanceModel {
+        self.distance_model
+    }
+    fn earn(&self, from: &City, to: &City, class: &BookingClass, tpm: Option<u32>) -> Earning {
+        let mul = if from.area == AreaCode::Japan && from.area == to.area {
+            // Japan domestic
+            2.0
+        } else if (from.area == AreaCode::Japan && (to.area == AreaCode::Asia || to.area == AreaCode::Oceania)) ||
+            (to.area == AreaCode::Japan && (from.area == AreaCode::Asia || from.area == AreaCode::Oceania)) {
+            if from.country == "RU" || to.country == "RU" {
+                // Russia (East of Ural) is considered as Europe
+                1.0
+            } else {
+                1.5
+            }
+        } else {
+            1.0
+        };
+
+        let extra = if from.area == AreaCode::Japan || to.area == AreaCode::Japan {
+            400
+        } else {
+            0
+        };
+        let class_mul = lookup(&JAL_CLASS_MUL, class).unwrap_or(0.0);
+        let (distance, distance_source) = self.distance(from, to, tpm);
+
+        Earning {
+            distance: distance.floor() as u32,
+            distance_source,
+            multiplier: Some(mul * class_mul),
+            bonus: extra,
+            points: (distance * mul * class_mul + extra as f64).floor() as u32
+        }
+    }
+}
+
+// Class multipliers of AAdvantage for the oneworld partner flights
+const AA_CLASS_MUL: [(&str, f64); 6] = [
+    ("FAP", 1.5), ("JCDIR", 1.25), ("WE", 1.1), ("YBH", 1.0),
+    ("KMLV", 0.75), ("SNQOGT", 0.5)
+];
+
+// American Airlines AAdvantage Loyalty Points (equal to the award miles on partner flights)
+pub struct AAdvantageLoyaltyPoints {
+    pub distance_model: DistanceModel
+}
+impl EarningProgram for AAdvantageLoyaltyPoints {
+    fn name(&self) -> &'static str {
+        "AAdvantage Loyalty Points"
+    }
+    fn distance_model(&self) -> DistanceModel {
+        self.distance_model
+    }
+    fn earn(&self, from: &City, to: &City, class: &BookingClass, tpm: Option<u32>) -> Earning {
+        let class_mul = lookup(&AA_CLASS_MUL, class).unwrap_or(0.0);
+        let (distance, distance_source) = self.distance(from, to, tpm);
+
+        Earning {
+            distance: distance.floor() as u32,
+            distance_source,
+            multiplier: Some(class_mul),
+            bonus: 0,
+            points: (distance * class_mul).floor() as u32
+        }
+    }
+}
+
+// Column of the band tables for the booking class:
+// 0: discount economy, 1: flexible economy, 2: premium economy, 3: business, 4: first
+const BA_FARE_COLUMN: [(&str, usize); 5] = [
+    ("FAP", 4), ("JCDIR", 3), ("WE", 2), ("YBH", 1), ("KMLVSNQOGT", 0)
+];
+const QANTAS_FARE_COLUMN: [(&str, usize); 5] = [
+    ("FAP", 4), ("JCDIR", 3), ("WE", 2), ("YBHKM", 1), ("LVSNQOGT", 0)
+];
+
+// Upper bound of the distance band (in mile) and the points earned in the band for each column
+const BA_TIER_POINT_BANDS: [(f64, [u32; 5]); 8] = [
+    (650.0, [5, 10, 20, 40, 60]), (1150.0, [10, 15, 30, 60, 90]),
+    (2000.0, [15, 20, 40, 80, 120]), (3000.0, [25, 35, 70, 140, 210]),
+    (4000.0, [30, 40, 80, 160, 240]), (5500.0, [35, 45, 90, 180, 270]),
+    (6500.0, [40, 55, 105, 210, 315]), (f64::MAX, [50, 70, 140, 280, 420])
+];
+const QANTAS_STATUS_CREDIT_ZONES: [(f64, [u32; 5]); 10] = [
+    (600.0, [5, 10, 15, 20, 30]), (1200.0, [10, 20, 30, 40, 60]),
+    (2400.0, [15, 30, 45, 60, 90]), (3600.0, [20, 40, 60, 80, 120]),
+    (4800.0, [25, 50, 75, 100, 150]), (5800.0, [30, 60, 90, 120, 180]),
+    (7000.0, [35, 70, 105, 140, 210]), (8400.0, [40, 80, 120, 160, 240]),
+    (9600.0, [45, 90, 135, 180, 270]), (f64::MAX, [50, 100, 150, 200, 300])
+];
+
+fn find_band(bands: &[(f64, [u32; 5])], column: Option<usize>, (distance, distance_source): (f64, DistanceSource)) -> Earning {
+    let mut points = 0;
+    if let Some(column) = column {
+        for (upper, band_points) in bands {
+            if distance <= *upper {
+                points = band_points[column];
+                break;
+            }
+        }
+    }
+
+    Earning {
+        distance: distance.floor() as u32,
+        distance_source,
+        multiplier: None,
+        bonus: 0,
+        points
+    }
+}
+
+// British Airways Executive Club tier points
+pub struct BaTierPoints {
+    pub distance_model: DistanceModel
+}
+impl EarningProgram for BaTierPoints {
+    fn name(&self) -> &'static str {
+        "BA tier points"
+    }
+    fn distance_model(&self) -> DistanceModel {
+        self.distance_model
+    }
+    fn earn(&self, from: &City, to: &City, class: &BookingClass, tpm: Option<u32>) -> Earning {
+        find_band(&BA_TIER_POINT_BANDS, lookup(&BA_FARE_COLUMN, class), self.distance(from, to, tpm))
+    }
+}
+
+// Qantas Frequent Flyer status credits
+pub struct QantasStatusCredits {
+    pub distance_model: DistanceModel
+}
+impl EarningProgram for QantasStatusCredits {
+    fn name(&self) -> &'static str {
+        "Qantas status credits"
+    }
+    fn distance_model(&self) -> DistanceModel {
+        self.distance_model
+    }
+    fn earn(&self, from: &City, to: &City, class: &BookingClass, tpm: Option<u32>) -> Earning {
+        find_band(&QANTAS_STATUS_CREDIT_ZONES, lookup(&QANTAS_FARE_COLUMN, class), self.distance(from, to, tpm))
+    }
+}
+
+pub const PROGRAM_NAMES: [&str; 4] = ["jal", "aa", "ba", "qf"];
+
+// Returns the program of the name, measuring the distance with the given model instead of the program's own one
+pub fn find_program(name: &str, distance_model: Option<DistanceModel>) -> Option<Box<dyn EarningProgram>> {
+    let model = |default| distance_model.unwrap_or(default);
+    match name {
+        "jal" => Some(Box::new(JalFlyOnPoints { distance_model: model(DistanceModel::Spherical) })),
+        "aa" => Some(Box::new(AAdvantageLoyaltyPoints { distance_model: model(DistanceModel::Spherical) })),
+        // Distance bands are sensitive to a few miles, so the bands are looked up by the geodesic distance
+        "ba" => Some(Box::new(BaTierPoints { distance_model: model(DistanceModel::Wgs84) })),
+        "qf" => Some(Box::new(QantasStatusCredits { distance_model: model(DistanceModel::Wgs84) })),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::fixture;
+
+    fn class(s: &str) -> BookingClass {
+        BookingClass::parse(s).unwrap()
+    }
+
+    #[test]
+    fn jal_points() {
+        let mut world = fixture::world();
+        let jal = find_program("jal", None).unwrap();
+        let city = |code: &str| world.find_airport(code).unwrap();
+        // Formula before the booking classes, where the class multiplier was 1.25 for all the segments
+        let expected = |from: &City, to: &City, mul: f64, bonus: f64| {
+            (City::calc_distance(from, to, DistanceModel::Spherical) * mul * 1.25 + bonus).floor() as u32
+        };
+
+        for (from, to, mul, bonus) in &[("HND", "NRT", 2.0, 400.0), ("HND", "HKG", 1.5, 400.0), ("SYD", "NRT", 1.5, 400.0),
+            ("HND", "LHR", 1.0, 400.0), ("HKG", "LHR", 1.0, 0.0)] {
+            let (from, to) = (city(from), city(to));
+            assert_eq!(jal.calc_point(from, to, &class("D"), None), expected(from, to, *mul, *bonus));
+            assert_eq!(jal.calc_point(to, from, &class("D"), None), expected(to, from, *mul, *bonus));
+        }
+
+        let hkg = city("HKG").index;
+        world.cities[hkg].country = String::from("RU");
+        let (hnd, hkg) = (world.find_airport("HND").unwrap(), &world.cities[hkg]);
+        assert_eq!(jal.calc_point(hnd, hkg, &class("D"), None), expected(hnd, hkg, 1.0, 400.0));
+    }
+
+    #[test]
+    fn class_multipliers() {
+        let world = fixture::world();
+        let (hkg, lhr) = (world.find_airport("HKG").unwrap(), world.find_airport("LHR").unwrap());
+        let jal = find_program("jal", None).unwrap();
+        let aa = find_program("aa", None).unwrap();
+
+        for (c, jal_mul, aa_mul) in &[("F", 1.5, 1.5), ("J", 1.25, 1.25), ("W", 1.0, 1.1), ("Y", 1.0, 1.0),
+            ("K", 0.7, 0.75), ("V", 0.5, 0.75), ("S", 0.5, 0.5), ("Q", 0.3, 0.5)] {
+            assert_eq!(jal.earn(hkg, lhr, &class(c), Some(6000)).points, (6000.0 * jal_mul) as u32, "{}", c);
+            assert_eq!(aa.earn(hkg, lhr, &class(c), Some(6000)).points, (6000.0 * aa_mul) as u32, "{}", c);
+        }
+    }
+
+    #[test]
+    fn distance_bands() {
+        for (distance, points) in &[(1.0, 5), (650.0, 5), (650.5, 10), (1150.0, 10), (6500.0, 40), (6500.5, 50), (20000.0, 50)] {
+            assert_eq!(find_band(&BA_TIER_POINT_BANDS, Some(0), (*distance, DistanceSource::Computed)).points, *points, "{}", distance);
+        }
+        for (distance, points) in &[(600.0, 30), (600.5, 60), (9600.0, 270), (9600.5, 300)] {
+            assert_eq!(find_band(&QANTAS_STATUS_CREDIT_ZONES, Some(4), (*distance, DistanceSource::Computed)).points, *points, "{}", distance);
+        }
+
+        // The column follows the booking class
+        let world = fixture::world();
+        let (hkg, lhr) = (world.find_airport("HKG").unwrap(), world.find_airport("LHR").unwrap());
+        let ba = find_program("ba", None).unwrap();
+        let qf = find_program("qf", None).unwrap();
+        let points = |program: &dyn EarningProgram, c: &str| program.earn(hkg, lhr, &class(c), Some(650)).points;
+        assert_eq!(["A", "D", "W", "Y", "L"].iter().map(|c| points(ba.as_ref(), c)).collect::<Vec<_>>(), vec![60, 40, 20, 10, 5]);
+        assert_eq!(["A", "D", "W", "M", "L"].iter().map(|c| points(qf.as_ref(), c)).collect::<Vec<_>>(), vec![60, 40, 30, 20, 10]);
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use super::City;
+use super::error::DataError;
+
+// File in the data directory overriding the region constraints
+const REGIONS_FILE: &str = "regions.json";
+// Constraints of oneworld, used if the data directory has no regions file
+const DEFAULT_REGIONS: &str = include_str!("../../data/regions.json");
+
+// Group of cities (given by the airport or city codes) restricting the routes visiting it
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonRegion {
+    name: String,
+    cities: Vec<String>,
+    max_visits: Option<usize>,
+    #[serde(default)]
+    must_return: bool,
+    #[serde(default)]
+    terminal: bool
+}
+
+pub struct Region {
+    pub name: String,
+    // Indices of the cities in the region
+    cities: HashSet<usize>,
+    // Number of times the region can be entered
+    pub max_visits: Option<usize>,
+    // The region must be left to the city from which it was entered
+    pub must_return: bool,
+    // The region can only be left by the last segment in the continent
+    pub terminal: bool
+}
+impl Region {
+    pub fn contains(&self, city: usize) -> bool {
+        self.cities.contains(&city)
+    }
+}
+
+// Loads the regions, ignoring the codes of the cities not in the dataset
+pub fn load_regions(data_dir: &str, cities: &[City]) -> Result<Vec<Region>, DataError> {
+    let path = Path::new(data_dir).join(REGIONS_FILE);
+    if !path.exists() {
+        return parse_regions(DEFAULT_REGIONS, REGIONS_FILE, cities);
+    }
+
+    let path = path.to_string_lossy().into_owned();
+    let text = fs::read_to_string(&path).map_err(|source| DataError::MissingFile { path: path.clone(), source })?;
+    parse_regions(&text, &path, cities)
+}
+
+pub fn parse_regions(text: &str, path: &str, cities: &[City]) -> Result<Vec<Region>, DataError> {
+    let regions: Vec<JsonRegion> = serde_json::from_str(text)
+        .map_err(|e| DataError::MalformedJson { path: String::from(path), message: e.to_string() })?;
+
+    Ok(regions.into_iter().map(|r| Region {
+        cities: cities.iter().filter(|c| r.cities.contains(&c.code) || r.cities.contains(&c.metro)).map(|c| c.index).collect(),
+        name: r.name,
+        max_visits: r.max_visits,
+        must_return: r.must_return,
+        terminal: r.terminal
+    }).collect())
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::Deserialize;
+use super::City;
+use super::error::DataError;
+use super::json;
+
+// File in the data directory listing the ticketed point mileages (TPM) of the city pairs
+const TPM_FILE: &str = "tpm.json";
+
+// TPM between two airports or cities, applied to both directions
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TpmEntry {
+    from: String,
+    to: String,
+    tpm: u32
+}
+
+#[derive(Default)]
+pub struct TpmTable {
+    // Keyed by the pair of the codes in the alphabetical order
+    mileages: HashMap<(String, String), u32>
+}
+impl TpmTable {
+    // Loads the table, which is empty if the data directory has no TPM file
+    pub fn load(data_dir: &str) -> Result<TpmTable, DataError> {
+        let path = Path::new(data_dir).join(TPM_FILE);
+        let mut mileages = HashMap::new();
+
+        if path.exists() {
+            let entries: Vec<TpmEntry> = json::load_from_json_file(&path.to_string_lossy())?;
+            for e in entries {
+                mileages.insert(TpmTable::key(&e.from, &e.to), e.tpm);
+            }
+        }
+
+        Ok(TpmTable { mileages })
+    }
+
+    fn key(a: &str, b: &str) -> (String, String) {
+        if a <= b { (String::from(a), String::from(b)) } else { (String::from(b), String::from(a)) }
+    }
+
+    // TPM between the airports, where the entry of the airports takes precedence over that of their cities
+    pub fn find(&self, from: &City, to: &City) -> Option<u32> {
+        if self.mileages.is_empty() {
+            return None;
+        }
+
+        [(&from.code, &to.code), (&from.code, &to.metro), (&from.metro, &to.code), (&from.metro, &to.metro)].iter()
+            .find_map(|(a, b)| self.mileages.get(&TpmTable::key(a, b)).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::fixture;
+    use crate::data::program::{DistanceSource, find_program};
+
+    #[test]
+    fn find_tpm() {
+        let dir = fixture::DataDir::new("tpm");
+        assert!(TpmTable::load(&dir.path).unwrap().mileages.is_empty());
+
+        dir.write(TPM_FILE, r#"[
+            {"from": "LHR", "to": "HKG", "tpm": 5990},
+            {"from": "TYO", "to": "LON", "tpm": 5959},
+            {"from": "NRT", "to": "LHR", "tpm": 6000},
+            {"from": "TYO", "to": "HKG", "tpm": 1823}
+        ]"#);
+        let table = TpmTable::load(&dir.path).unwrap();
+        let world = fixture::world();
+        let tpm = |from: &str, to: &str| table.find(world.find_airport(from).unwrap(), world.find_airport(to).unwrap());
+
+        assert_eq!(TpmTable::key("LHR", "HKG"), TpmTable::key("HKG", "LHR"));
+        assert_eq!((tpm("HKG", "LHR"), tpm("LHR", "HKG")), (Some(5990), Some(5990)));
+        // The entry of the airports takes precedence over that of their cities
+        assert_eq!((tpm("NRT", "LHR"), tpm("LHR", "NRT")), (Some(6000), Some(6000)));
+        assert_eq!((tpm("HND", "LHR"), tpm("LHR", "HND")), (Some(5959), Some(5959)));
+        assert_eq!((tpm("HKG", "NRT"), tpm("HND", "HKG")), (Some(1823), Some(1823)));
+        assert_eq!(tpm("HND", "DFW"), None);
+
+        let jal = find_program("jal", None).unwrap();
+        let (hnd, lhr, dfw) = (world.find_airport("HND").unwrap(), world.find_airport("LHR").unwrap(), world.find_airport("DFW").unwrap());
+        let (distance, source) = jal.distance(hnd, lhr, table.find(hnd, lhr));
+        assert!(distance == 5959.0 && source == DistanceSource::Tpm);
+        let (distance, source) = jal.distance(hnd, dfw, table.find(hnd, dfw));
+        assert!(distance == City::calc_distance(hnd, dfw, jal.distance_model()) && source == DistanceSource::Computed);
+    }
+}
@@ -1,98 +1,281 @@
-use std::collections::{HashMap, HashSet};
-use super::data::{AreaCode, CityData, City};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use super::data::{CityData, City, Continent};
+use super::data::error::LookupError;
+use super::rule::{check_regions, find_crossing, max_segments_in_continent, surface_distance, validate_route, Ocean, MAX_CONTINENTS, MAX_SEGMENTS,
+    MIN_CONTINENTS};
+
+pub enum FindError {
+    Lookup(LookupError),
+    UnknownArea(String),
+    DifferentContinents(String, String),
+    BoundaryCount(usize, usize),
+    BoundaryContinent(String, Continent),
+    OriginContinent(String, Continent),
+    DuplicateContinent(Continent),
+    ContinentCount(usize),
+    TopCount,
+    Crossings(Ocean, usize),
+    NoRoute
+}
+impl fmt::Display for FindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FindError::Lookup(e) => write!(f, "{}", e),
+            FindError::UnknownArea(area) =>
+                write!(f, "Unrecognized area: {} (available: {})", area, Continent::NAMES.join(", ")),
+            FindError::DifferentContinents(from, to) =>
+                write!(f, "Origin {} and destination {} are not in the same continent", from, to),
+            FindError::BoundaryCount(expected, actual) =>
+                write!(f, "{} boundary cities are required but {} are given", expected, actual),
+            FindError::BoundaryContinent(code, continent) => write!(f, "{} is not in {}", code, continent.name()),
+            FindError::OriginContinent(code, continent) =>
+                write!(f, "The origin {} is not in the first continent {}", code, continent.name()),
+            FindError::DuplicateContinent(continent) => write!(f, "{} is given more than once", continent.name()),
+            FindError::ContinentCount(n) =>
+                write!(f, "{} to {} continents are required but {} are given", MIN_CONTINENTS, MAX_CONTINENTS, n),
+            FindError::TopCount => write!(f, "At least one route must be requested"),
+            FindError::Crossings(ocean, n) => write!(f, "The {} must be crossed once, but the route crosses it {} times", ocean.name(), n),
+            FindError::NoRoute => write!(f, "Cannot find any route for the combination")
+        }
+    }
+}
+
+impl fmt::Debug for FindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+impl std::error::Error for FindError {}
+
+// Route found by the search, as a list of city indices
+#[derive(Clone)]
+pub struct RouteResult {
+    pub points: u32,
+    pub route: Vec<usize>
+}
+
+pub struct IntercontinentalResult {
+    pub points: u32,
+    pub from: usize,
+    pub to: usize
+}
+
+fn find_city<'a>(city_data: &'a CityData, code: &str) -> Result<&'a City, FindError> {
+    city_data.find_airport(code).map_err(FindError::Lookup)
+}
 
 fn city(city_data: &CityData, index: usize) -> &City {
     &city_data.cities[index]
 }
 
-fn prepare_area_data(city_data: &CityData) -> (HashMap<String, &City>, HashSet<usize>, HashSet<usize>, HashSet<usize>) {
-    let mut city_map = HashMap::new();
-    let mut cities_asia = HashSet::new();
-    let mut cities_europe = HashSet::new();
-    let mut cities_na = HashSet::new();
+fn prepare_area_data(city_data: &CityData) -> HashMap<Continent, HashSet<usize>> {
+    let mut area_sets = HashMap::new();
 
+    for continent in &Continent::ALL {
+        area_sets.insert(*continent, HashSet::new());
+    }
     for i in 0..city_data.cities.len() {
-        city_map.insert(city_data.cities[i].code.clone(), &city_data.cities[i]);
-
-        match city_data.cities[i].area {
-            AreaCode::Asia | AreaCode::Japan => cities_asia.insert(i),
-            AreaCode::EuropeMiddleEast => cities_europe.insert(i),
-            AreaCode::NorthAmerica => cities_na.insert(i),
-            _ => false
-        };
+        area_sets.get_mut(&city_data.cities[i].area.continent()).unwrap().insert(i);
     }
 
-    (city_map, cities_asia, cities_europe, cities_na)
+    area_sets
 }
 
-fn find_route_in_continent(city_data: &CityData, cities: &HashSet<usize>, num: usize, from: &City, to: &City) -> Option<(u32, Vec<usize>)> {
-    match find_route_in_continent_hub_core(city_data, cities, num, from, to, &[from]) {
-        Some((max_distance, mut rev_route)) => {
-            rev_route.reverse();
+fn find_continent(name: &str) -> Result<Continent, FindError> {
+    Continent::from_name(name).ok_or_else(|| FindError::UnknownArea(String::from(name)))
+}
 
-            Some((max_distance, rev_route))
-        },
-        None => None
+// Adds the route to the list of the best routes, which is kept sorted by points
+// (ties are broken by the route itself so that the order is deterministic) and at most `top` long
+fn add_result(results: &mut Vec<RouteResult>, top: usize, result: RouteResult) {
+    let pos = results.partition_point(|r| r.points > result.points || (r.points == result.points && r.route < result.route));
+    if pos < top {
+        results.insert(pos, result);
+        results.truncate(top);
     }
 }
 
-fn check_rule(history: &[&City], current: usize, next: usize) -> bool {
-    let mut hawaii = false;
+// Search of the best routes within a continent, reused for all the queries in the continent
+struct ContinentSearch<'a> {
+    city_data: &'a CityData,
+    // Routes from each city to the cities in the continent, longest first,
+    // followed by the surface sectors (without points) to the cities without a flight
+    neighbours: Vec<Vec<(usize, u32)>>,
+    // For each destination, the most points achievable from each city with the given number of segments
+    // (ignoring the rules), used as the upper bound to prune the search
+    bounds: HashMap<usize, Vec<Vec<Option<u32>>>>,
+    top: usize,
+    threads: usize
+}
+
+// State of a single search, where the current route is kept as a stack
+struct SearchState<'a> {
+    city_data: &'a CityData,
+    neighbours: &'a [Vec<(usize, u32)>],
+    bound: &'a [Vec<Option<u32>>],
+    top: usize,
+    path: Vec<usize>,
+    results: Vec<RouteResult>
+}
 
-    for i in 0..history.len() {
-        if i + 1 < history.len() {
-            if history[i].index == current && history[i + 1].index == next {
-                return false;
+impl<'a> ContinentSearch<'a> {
+    fn new(city_data: &'a CityData, cities: &HashSet<usize>, top: usize, threads: usize, max_surface: Option<u32>) -> ContinentSearch<'a> {
+        let mut neighbours = vec![Vec::new(); city_data.cities.len()];
+        let sorted = sorted_cities(cities);
+
+        for i in sorted.iter().cloned() {
+            let from = city(city_data, i);
+            neighbours[i] = from.routes().iter().filter(|r| cities.contains(&r.to)).map(|r| (r.to, r.points)).collect();
+            neighbours[i].sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+            if let Some(max) = max_surface {
+                for j in sorted.iter().cloned() {
+                    let to = city(city_data, j);
+                    if i != j && from.route(to).is_none() && surface_distance(from, to) <= max {
+                        neighbours[i].push((j, 0));
+                    }
+                }
             }
         }
-        if history[i].code == "HNL" || history[i].code == "OGG" {
-            hawaii = true;
-        } else if hawaii {
-            return false;
+
+        ContinentSearch { city_data, neighbours, bounds: HashMap::new(), top, threads }
+    }
+
+    fn update_bounds(&mut self, to: usize, segments: usize) {
+        let neighbours = &self.neighbours;
+        let bound = self.bounds.entry(to).or_insert_with(|| {
+            let mut first = vec![None; neighbours.len()];
+            first[to] = Some(0);
+            vec![first]
+        });
+
+        while bound.len() <= segments {
+            let prev = bound.last().unwrap();
+            let next = neighbours.iter().map(|list| {
+                list.iter().filter_map(|(j, d)| prev[*j].map(|b| b + d)).max()
+            }).collect();
+            bound.push(next);
         }
     }
 
-    true
-}
+    // Best routes from `from` to `to` with the given number of segments
+    fn find(&mut self, from: usize, to: usize, segments: usize) -> Vec<RouteResult> {
+        self.update_bounds(to, segments);
 
-fn find_route_in_continent_hub_core(city_data: &CityData, cities: &HashSet<usize>, num: usize, from: &City, to: &City, hist: &[&City]) -> Option<(u32, Vec<usize>)> {
-    let mut max_distance = 0;
-    let mut max_route = Vec::new();
+        self.search(from, to, segments, self.threads)
+    }
+
+    // Same as `find`, but the bounds to the destination must have been computed
+    fn search(&self, from: usize, to: usize, segments: usize, threads: usize) -> Vec<RouteResult> {
+        if segments == 0 {
+            // Staying in a single city consumes no segment
+            return if from == to {
+                vec![RouteResult { points: 0, route: vec![from] }]
+            } else {
+                Vec::new()
+            };
+        }
 
-    if num == 0 {
-        return if check_rule(hist, from.index, to.index) {
-            Some((from.distance(to), Vec::from([to.index, from.index])))
-        } else {
-            None
+        let bound = &self.bounds[&to];
+        if bound[segments][from].is_none() {
+            return Vec::new();
+        }
+        let new_state = |path| SearchState {
+            city_data: self.city_data,
+            neighbours: &self.neighbours,
+            bound,
+            top: self.top,
+            path,
+            results: Vec::new()
         };
-    }
 
-    for i in cities {
-        let c = city(city_data, *i);
+        if threads <= 1 {
+            let mut state = new_state(vec![from]);
+            find_route_in_continent_core(&mut state, segments, 0);
+            return state.results;
+        }
 
-        if from.distance(c) > 0 && cities.contains(i) {
-            if check_rule(hist, from.index, *i) {
+        // The routes through each of the first stops are searched in parallel
+        let branches: Vec<(usize, u32)> = self.neighbours[from].iter()
+            .filter(|(next, _)| bound[segments - 1][*next].is_some() && check_rule(self.city_data, &[from], *next, segments == 1))
+            .cloned()
+            .collect();
+        let lists = run_parallel(threads, &branches, |(next, d)| {
+            let mut state = new_state(vec![from, *next]);
+            find_route_in_continent_core(&mut state, segments - 1, *d);
+            state.results
+        });
+
+        let mut results = Vec::new();
+        for r in lists.into_iter().flatten() {
+            add_result(&mut results, self.top, r);
+        }
 
-                let result = find_route_in_continent_hub_core(city_data, cities, num - 1, c, to,
-                    &[hist, &[c]].concat());
+        results
+    }
+}
 
-                if let Some((distance, route)) = result {
-                    let total = from.distance(c) + distance;
-                    if total > max_distance {
-                        max_distance = total;
-                        max_route = route;
-                    }
+// Runs the function for each job on the given number of threads, returning the results in the order of the jobs
+fn run_parallel<T: Sync, R: Send, F: Fn(&T) -> R + Sync>(threads: usize, jobs: &[T], f: F) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads.min(jobs.len())).map(|_| s.spawn(|| {
+            let mut done = Vec::new();
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= jobs.len() {
+                    break;
                 }
+                done.push((i, f(&jobs[i])));
             }
-        }
+            done
+        })).collect();
+
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+// Checks if the route can continue to the next city
+fn check_rule(city_data: &CityData, path: &[usize], next: usize, last: bool) -> bool {
+    let current = city(city_data, path[path.len() - 1]);
+    let next_city = city(city_data, next);
+    if path.windows(2).any(|w| city(city_data, w[0]).metro == current.metro && city(city_data, w[1]).metro == next_city.metro) {
+        return false;
     }
 
-    if max_route.len() > 0 {
-        max_route.push(from.index);
+    check_regions(city_data, path, next, last).is_none()
+}
 
-        Some((max_distance, max_route))
-    } else {
-        None
+fn find_route_in_continent_core(state: &mut SearchState, remaining: usize, points: u32) {
+    if remaining == 0 {
+        add_result(&mut state.results, state.top, RouteResult { points, route: state.path.clone() });
+        return;
+    }
+
+    let current = state.path[state.path.len() - 1];
+    let neighbours = state.neighbours;
+    for (next, d) in &neighbours[current] {
+        let bound = match state.bound[remaining - 1][*next] {
+            Some(b) => b,
+            None => continue
+        };
+        // Routes tied with the worst of the best routes are kept to break the tie deterministically
+        if state.results.len() == state.top && points + d + bound < state.results[state.top - 1].points {
+            continue;
+        }
+        if !check_rule(state.city_data, &state.path, *next, remaining == 1) {
+            continue;
+        }
+
+        state.path.push(*next);
+        find_route_in_continent_core(state, remaining - 1, points + d);
+        state.path.pop();
     }
 }
 
@@ -100,14 +283,8 @@ fn find_boundary(city_data: &CityData, set_from: &HashSet<usize>, set_to: &HashS
     let mut boundary_set = HashSet::new();
 
     for i in set_from {
-        for j in set_to {
-            let from = city(city_data, *i);
-            let to = city(city_data, *j);
-
-            if from.distance(to) > 0 {
-                boundary_set.insert(*i);
-                break;
-            }
+        if city(city_data, *i).routes().iter().any(|r| set_to.contains(&r.to)) {
+            boundary_set.insert(*i);
         }
     }
 
@@ -118,13 +295,9 @@ fn find_longest_intercontinental_routes(city_data: &CityData, set_from: &HashSet
     let mut result = Vec::new();
 
     for i in set_from {
-        for j in set_to {
-            let from = city(city_data, *i);
-            let to = city(city_data, *j);
-
-            let d = from.distance(to);
-            if d > 0 {
-                result.push((d, *i, *j));
+        for r in city(city_data, *i).routes() {
+            if set_to.contains(&r.to) {
+                result.push((r.points, *i, r.to));
             }
         }
     }
@@ -135,112 +308,710 @@ fn find_longest_intercontinental_routes(city_data: &CityData, set_from: &HashSet
 }
 
 
-pub fn find_intercontinental_route(city_data: CityData, area1: &str, area2: &str){
-    let (_, cities_asia, cities_europe, cities_na) = prepare_area_data(&city_data);
+pub fn find_intercontinental_route(city_data: &CityData, area1: &str, area2: &str) -> Result<Vec<IntercontinentalResult>, FindError> {
+    let area_sets = prepare_area_data(city_data);
 
-    let area_set1 = match area1 {
-        "asia" => &cities_asia,
-        "europe" => &cities_europe,
-        "na" => &cities_na,
-        _ => panic!("Unrecognized area: {}", area1)
-    };
-    let area_set2 = match area2 {
-        "asia" => &cities_asia,
-        "europe" => &cities_europe,
-        "na" => &cities_na,
-        _ => panic!("Unrecognized area: {}", area2)
-    };
+    let area_set1 = &area_sets[&find_continent(area1)?];
+    let area_set2 = &area_sets[&find_continent(area2)?];
 
-    let boundaries = find_boundary(&city_data, area_set1, area_set2);
-    let mut routes = find_longest_intercontinental_routes(&city_data, &boundaries, area_set2);
+    let boundaries = find_boundary(city_data, area_set1, area_set2);
+    let mut routes = find_longest_intercontinental_routes(city_data, &boundaries, area_set2);
     routes.reverse();
-    
-    for (distance, city1, city2) in routes.iter().take(10) {
-        println!("{} -> {}: {}", city(&city_data, *city1).code, city(&city_data, *city2).code, distance);
+    if routes.is_empty() {
+        return Err(FindError::NoRoute);
     }
+
+    Ok(routes.iter().take(10).map(|(points, from, to)| IntercontinentalResult {
+        points: *points, from: *from, to: *to
+    }).collect())
 }
 
-pub fn find_continental_route(city_data: CityData, from: &str, to: &str, num: usize) {
-    let (city_map, cities_asia, cities_europe, cities_na) = prepare_area_data(&city_data);
-    let from = city_map[from];
-    let to = city_map[to];
+pub fn find_continental_route(city_data: &CityData, from: &str, to: &str, num: usize, top: usize, threads: usize, max_surface: Option<u32>)
+    -> Result<Vec<RouteResult>, FindError> {
+    check_top(top)?;
+    let area_sets = prepare_area_data(city_data);
+    let from = find_city(city_data, from)?;
+    let to = find_city(city_data, to)?;
 
-    if from.area != to.area && (from.area != AreaCode::Asia || to.area != AreaCode::Japan) &&
-        (from.area != AreaCode::Japan || to.area != AreaCode::Asia) {
-        panic!("Origin and destination are not in the same continent");
+    if from.area.continent() != to.area.continent() {
+        return Err(FindError::DifferentContinents(from.code.clone(), to.code.clone()));
     }
+    let area = &area_sets[&from.area.continent()];
 
-    let area = match from.area {
-        AreaCode::Asia | AreaCode::Japan => &cities_asia,
-        AreaCode::EuropeMiddleEast => &cities_europe,
-        AreaCode::NorthAmerica => &cities_na,
-        _ => panic!("Unsupported area")
-    };
+    let results = ContinentSearch::new(city_data, area, top, threads, max_surface).find(from.index, to.index, num + 1);
+    if results.is_empty() {
+        return Err(FindError::NoRoute);
+    }
+
+    Ok(results)
+}
 
-    let result = find_route_in_continent(&city_data, area, num, from, to);
+fn concat_result(a: &RouteResult, b: &RouteResult, extra: u32) -> RouteResult {
+    RouteResult {
+        points: a.points + b.points + extra,
+        route: [a.route.as_slice(), b.route.as_slice()].concat()
+    }
+}
+
+fn check_top(top: usize) -> Result<(), FindError> {
+    if top == 0 {
+        return Err(FindError::TopCount);
+    }
 
-    if let Some((distance, route)) = result {
-        println!("Distance = {}", distance);
+    Ok(())
+}
 
-        for p in route {
-            print!("{} - ", city(&city_data, p).code);
+// Checks that the journey visits a valid number of distinct continents
+fn check_continents(continents: &[Continent]) -> Result<(), FindError> {
+    for (i, c) in continents.iter().enumerate() {
+        if continents[..i].contains(c) {
+            return Err(FindError::DuplicateContinent(*c));
         }
-        println!("");
-    } else {
-        println!("Cannot find any route for the combination");
     }
+    if continents.len() < MIN_CONTINENTS || continents.len() > MAX_CONTINENTS {
+        return Err(FindError::ContinentCount(continents.len()));
+    }
+
+    Ok(())
 }
 
-fn find_route_continents(city_data: &CityData, area_sets: &[&HashSet<usize>], boundaries: &[&City], limits: &[usize]) -> (u32, Vec<usize>) {
-    let mut final_routes = Vec::new();
+// Surface sectors of at most `max_surface` miles can be used within a continent
+pub fn find_route(city_data: &CityData, continents: &[Continent], boundaries: &[&str], top: usize, threads: usize, max_surface: Option<u32>)
+    -> Result<Vec<RouteResult>, FindError> {
+    check_top(top)?;
+    let area_sets = prepare_area_data(city_data);
 
-    for i in 0..area_sets.len() {
-        let result = find_route_in_continent(&city_data, area_sets[i], limits[i] - 1, boundaries[i * 2], boundaries[i * 2 + 1]);
+    check_continents(continents)?;
+    if boundaries.len() != continents.len() * 2 {
+        return Err(FindError::BoundaryCount(continents.len() * 2, boundaries.len()));
+    }
+    let mut boundary_cities = Vec::new();
+    for (i, b) in boundaries.iter().enumerate() {
+        let c = find_city(city_data, b)?;
+        if c.area.continent() != continents[i / 2] {
+            return Err(FindError::BoundaryContinent(c.code.clone(), continents[i / 2]));
+        }
+        boundary_cities.push(c);
+    }
 
-        if let Some((distance, mut routes)) = result {
-            final_routes.append(&mut routes);
-        } else {
-            panic!("No route found inside the continent");
+    // Intercontinental segments between the continents
+    let mut crossing_points = 0;
+    let mut oceans = Vec::new();
+    for i in 0..continents.len() - 1 {
+        let exit = boundary_cities[i * 2 + 1];
+        let entry = boundary_cities[i * 2 + 2];
+        match exit.route(entry) {
+            Some(r) => crossing_points += r.points,
+            None => return Err(FindError::NoRoute)
         }
+        oceans.extend(find_crossing(exit, entry));
+    }
+    // Return to any airport of the origin city
+    let last = boundary_cities[boundary_cities.len() - 1];
+    let ends: Vec<(&City, u32)> = city_data.cities.iter()
+        .filter(|c| c.metro == boundary_cities[0].metro)
+        .filter_map(|c| last.route(c).map(|r| (c, r.points)))
+        .collect();
+    match ends.first() {
+        Some((end, _)) => oceans.extend(find_crossing(last, end)),
+        None => return Err(FindError::NoRoute)
+    }
+    for ocean in &Ocean::ALL {
+        let count = oceans.iter().filter(|o| *o == ocean).count();
+        if count != 1 {
+            return Err(FindError::Crossings(*ocean, count));
+        }
+    }
+    let budget = MAX_SEGMENTS.saturating_sub(continents.len());
+
+    // Best routes in each continent for every number of segments, searched in parallel
+    let searches: Vec<ContinentSearch> = continents.iter().enumerate().map(|(i, continent)| {
+        let mut search = ContinentSearch::new(city_data, &area_sets[continent], top, 1, max_surface);
+        search.update_bounds(boundary_cities[i * 2 + 1].index, max_segments_in_continent(*continent));
+        search
+    }).collect();
+    let jobs: Vec<(usize, usize)> = continents.iter().enumerate()
+        .flat_map(|(i, continent)| (0..=max_segments_in_continent(*continent)).map(move |num| (i, num)))
+        .collect();
+    let mut continent_routes: Vec<Vec<Vec<RouteResult>>> = vec![Vec::new(); continents.len()];
+    let lists = run_parallel(threads, &jobs, |(i, num)| {
+        searches[*i].search(boundary_cities[i * 2].index, boundary_cities[i * 2 + 1].index, *num, 1)
+    });
+    for ((i, _), routes) in jobs.iter().zip(lists) {
+        continent_routes[*i].push(routes);
     }
 
-    final_routes.push(boundaries[0].index);
+    // Best routes for every number of segments used so far, choosing the number of segments in each continent
+    let mut states: Vec<Vec<RouteResult>> = vec![Vec::new(); budget + 1];
+    states[0].push(RouteResult { points: 0, route: Vec::new() });
 
-    let mut distance = 0;
-    for i in 0..final_routes.len() - 1 {
-        distance += city(city_data, final_routes[i]).distance(city(city_data, final_routes[i + 1]));
+    for routes_by_num in &continent_routes {
+        let mut next_states: Vec<Vec<RouteResult>> = vec![Vec::new(); budget + 1];
+
+        for (num, routes) in routes_by_num.iter().enumerate() {
+            for used in 0..=budget.saturating_sub(num) {
+                for partial in &states[used] {
+                    for r in routes {
+                        add_result(&mut next_states[used + num], top, concat_result(partial, r, 0));
+                    }
+                }
+            }
+        }
+        states = next_states;
     }
 
-    (distance, final_routes)
+    let mut results = Vec::new();
+    for state in &states {
+        for r in state {
+            for (end, points) in &ends {
+                let mut route = r.route.clone();
+                route.push(end.index);
+
+                // The continents are searched apart, so the rules across them are checked on the whole route
+                if is_valid(city_data, &route, max_surface) {
+                    add_result(&mut results, top, RouteResult { points: r.points + crossing_points + points, route });
+                }
+            }
+        }
+    }
+
+    if results.is_empty() {
+        return Err(FindError::NoRoute);
+    }
+
+    Ok(results)
 }
 
-pub fn find_route(city_data: CityData, _mode: &str, boundaries: &[&str]) {
-    let (city_map, cities_asia, cities_europe, cities_na) = prepare_area_data(&city_data);
+// Best routes in a continent between any two cities, computed on demand
+struct ContinentTable<'a> {
+    search: ContinentSearch<'a>,
+    threads: usize,
+    routes: HashMap<(usize, usize, usize), Vec<RouteResult>>
+}
+impl<'a> ContinentTable<'a> {
+    fn new(city_data: &'a CityData, cities: &'a HashSet<usize>, top: usize, threads: usize, max_surface: Option<u32>) -> ContinentTable<'a> {
+        ContinentTable { search: ContinentSearch::new(city_data, cities, top, 1, max_surface), threads, routes: HashMap::new() }
+    }
 
-    let mut boundary_cities = Vec::new();
-    for b in boundaries {
-        boundary_cities.push(city_map[*b]);
+    // Computes the routes for all the combinations of the cities and the numbers of segments in parallel
+    fn prefetch(&mut self, from: &[usize], to: &[usize], max: usize) {
+        let mut queries = Vec::new();
+        for t in to {
+            self.search.update_bounds(*t, max);
+            for f in from {
+                for num in 0..=max {
+                    if !self.routes.contains_key(&(*f, *t, num)) {
+                        queries.push((*f, *t, num));
+                    }
+                }
+            }
+        }
+
+        let search = &self.search;
+        let lists = run_parallel(self.threads, &queries, |(from, to, num)| search.search(*from, *to, *num, 1));
+        self.routes.extend(queries.into_iter().zip(lists));
+    }
+
+    fn get(&mut self, from: usize, to: usize, segments: usize) -> &Vec<RouteResult> {
+        let search = &mut self.search;
+
+        self.routes.entry((from, to, segments))
+            .or_insert_with(|| search.find(from, to, segments))
     }
+}
+
+// Cities in the set which have a route to any city in the other set
+fn find_gateways(city_data: &CityData, set_from: &HashSet<usize>, set_to: &HashSet<usize>) -> Vec<usize> {
+    let mut gateways: Vec<usize> = find_boundary(city_data, set_from, set_to).into_iter().collect();
+    gateways.sort_unstable();
+
+    gateways
+}
+
+fn sorted_cities(cities: &HashSet<usize>) -> Vec<usize> {
+    let mut v: Vec<usize> = cities.iter().cloned().collect();
+    v.sort_unstable();
+
+    v
+}
+
+// Number of the routes searched at least for the return to the origin
+const MIN_RETURN_CANDIDATES: usize = 10;
+
+// Checks the whole route against the rules, where the segments without a flight are surface sectors
+fn is_valid(city_data: &CityData, route: &[usize], max_surface: Option<u32>) -> bool {
+    let surface: Vec<bool> = route.windows(2).map(|w| city(city_data, w[0]).route(city(city_data, w[1])).is_none()).collect();
+
+    validate_route(city_data, route, &surface, max_surface).is_empty()
+}
 
-    let results = [
-        find_route_continents(&city_data, &[&cities_asia, &cities_europe, &cities_na], &boundary_cities, &[4, 4, 5]),
-        find_route_continents(&city_data, &[&cities_asia, &cities_europe, &cities_na], &boundary_cities, &[4, 3, 6]),
-        find_route_continents(&city_data, &[&cities_asia, &cities_europe, &cities_na], &boundary_cities, &[3, 4, 6]),
-    ];
+// Best partial routes for each key of (last city, segments used, segments used in the origin continent, oceans crossed)
+type States = BTreeMap<(usize, usize, usize, Crossed), Vec<RouteResult>>;
 
-    let mut max_distance = 0;
-    let mut longest_route = &Vec::new();
-    for r in &results {
-        if r.0 > max_distance {
-            max_distance = r.0;
-            longest_route = &r.1;
+// Oceans crossed so far, indexed as in `Ocean::ALL`
+type Crossed = [bool; 2];
+
+// Oceans crossed after the intercontinental segment, or None if it crosses an ocean twice
+fn cross(city_data: &CityData, crossed: Crossed, from: usize, to: usize) -> Option<Crossed> {
+    match find_crossing(city(city_data, from), city(city_data, to)) {
+        Some(ocean) => {
+            let i = Ocean::ALL.iter().position(|o| *o == ocean).unwrap();
+            let mut next = crossed;
+            next[i] = true;
+            if crossed[i] { None } else { Some(next) }
+        },
+        None => Some(crossed)
+    }
+}
+
+// Cities in the set reachable from the last city of any partial route
+fn reachable_entries(city_data: &CityData, states: &States, cities: &HashSet<usize>) -> Vec<usize> {
+    sorted_cities(cities).into_iter()
+        .filter(|entry| states.keys().any(|(last, _, _, _)| city(city_data, *last).route(city(city_data, *entry)).is_some()))
+        .collect()
+}
+
+// Finds the best round-the-world routes from the origin through the continents in the given order,
+// choosing the gateway cities between the continents and the number of segments in each continent
+// (surface sectors of at most `max_surface` miles can be used within a continent)
+pub fn find_route_auto(city_data: &CityData, continents: &[Continent], origin: &str, top: usize, threads: usize, max_surface: Option<u32>)
+    -> Result<Vec<RouteResult>, FindError> {
+    check_top(top)?;
+    let area_sets = prepare_area_data(city_data);
+    let origin = find_city(city_data, origin)?;
+
+    if continents.is_empty() || origin.area.continent() != continents[0] {
+        return Err(FindError::OriginContinent(origin.code.clone(), continents.first().cloned().unwrap_or(Continent::Asia)));
+    }
+    check_continents(continents)?;
+
+    let origin_set = &area_sets[&continents[0]];
+    let origin_max = max_segments_in_continent(continents[0]);
+    // More routes are kept in the origin continent to replace the returns conflicting with the outbound routes
+    let mut origin_table = ContinentTable::new(city_data, origin_set, top.max(MIN_RETURN_CANDIDATES), threads, max_surface);
+
+    // Segments in the origin continent before leaving it
+    let mut states = States::new();
+    let exits = find_gateways(city_data, origin_set, &area_sets[&continents[1]]);
+    origin_table.prefetch(&[origin.index], &exits, origin_max);
+    for exit in exits {
+        for num in 0..=origin_max {
+            for r in origin_table.get(origin.index, exit, num) {
+                add_result(states.entry((exit, num, num, [false; 2])).or_default(), top, r.clone());
+            }
         }
     }
 
-    println!("Distance = {}", max_distance);
+    // Each of the other continents, from the entry city to the exit city
+    for i in 1..continents.len() {
+        let cities = &area_sets[&continents[i]];
+        let next_set = &area_sets[&continents[(i + 1) % continents.len()]];
+        let max = max_segments_in_continent(continents[i]);
+        let entries = reachable_entries(city_data, &states, cities);
+        let exits = find_gateways(city_data, cities, next_set);
+        let mut table = ContinentTable::new(city_data, cities, top, threads, max_surface);
+        table.prefetch(&entries, &exits, max);
+
+        let mut next_states = States::new();
+        for ((last, used, origin_used, crossed), partials) in &states {
+            for entry in &entries {
+                let crossing = match city(city_data, *last).route(city(city_data, *entry)) {
+                    Some(r) => r.points,
+                    None => continue
+                };
+                let crossed = match cross(city_data, *crossed, *last, *entry) {
+                    Some(c) => c,
+                    None => continue
+                };
+                for exit in &exits {
+                    for num in 0..=max {
+                        if used + 1 + num > MAX_SEGMENTS {
+                            break;
+                        }
+                        let list = next_states.entry((*exit, used + 1 + num, *origin_used, crossed)).or_default();
+                        for r in table.get(*entry, *exit, num) {
+                            for partial in partials {
+                                add_result(list, top, concat_result(partial, r, crossing));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        states = next_states;
+    }
+
+    // Return to the origin continent and to any airport of the origin city
+    let mut results: Vec<RouteResult> = Vec::new();
+    let entries = reachable_entries(city_data, &states, origin_set);
+    let ends: Vec<usize> = sorted_cities(origin_set).into_iter().filter(|i| city(city_data, *i).metro == origin.metro).collect();
+    origin_table.prefetch(&entries, &ends, origin_max);
+    let mut returns = HashMap::new();
+    for entry in &entries {
+        for num in 0..=origin_max {
+            let mut list: Vec<RouteResult> = ends.iter().flat_map(|end| origin_table.get(*entry, *end, num).clone()).collect();
+            list.sort_by(|a, b| b.points.cmp(&a.points).then_with(|| a.route.cmp(&b.route)));
+            returns.insert((*entry, num), list);
+        }
+    }
+    for ((last, used, origin_used, crossed), partials) in &states {
+        for entry in &entries {
+            let crossing = match city(city_data, *last).route(city(city_data, *entry)) {
+                Some(r) => r.points,
+                None => continue
+            };
+            // Both the oceans must have been crossed once by the end of the journey
+            if cross(city_data, *crossed, *last, *entry) != Some([true; 2]) {
+                continue;
+            }
+            for num in 0..=(origin_max - origin_used) {
+                if used + 1 + num > MAX_SEGMENTS {
+                    break;
+                }
+                // The return is searched apart from the outbound route, so the rules across them are checked
+                // on the whole route, falling back to the next best return if it fails
+                for partial in partials {
+                    let mut added = 0;
+                    for r in &returns[&(*entry, num)] {
+                        let result = concat_result(partial, r, crossing);
+                        if added == top || (results.len() == top && result.points < results[top - 1].points) {
+                            break;
+                        }
+                        if is_valid(city_data, &result.route, max_surface) {
+                            add_result(&mut results, top, result);
+                            added += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if results.is_empty() {
+        return Err(FindError::NoRoute);
+    }
+
+    Ok(results)
+}
+
+// Direction of travel around the world
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Direction {
+    Eastbound,
+    Westbound
+}
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Eastbound => "Eastbound",
+            Direction::Westbound => "Westbound"
+        }
+    }
+}
+
+// Best route for an order of the continents
+pub struct PlanResult {
+    pub continents: Vec<Continent>,
+    // None if no route is found in the order
+    pub direction: Option<Direction>,
+    pub route: Option<RouteResult>
+}
+
+fn is_america(continent: Continent) -> bool {
+    continent == Continent::NorthAmerica || continent == Continent::SouthAmerica
+}
+
+// Checks if the order can cross each ocean once, i.e. the Americas are visited in a row
+// (which ocean is crossed on the way is left to the search)
+fn crosses_americas_once(continents: &[Continent]) -> bool {
+    let n = continents.len();
+    let entries = (0..n).filter(|i| !is_america(continents[*i]) && is_america(continents[(i + 1) % n])).count();
+    let exits = (0..n).filter(|i| is_america(continents[*i]) && !is_america(continents[(i + 1) % n])).count();
+
+    entries == 1 && exits == 1
+}
+
+// Direction of the route, given by whether it crosses the Pacific into or out of the Americas
+fn find_direction(city_data: &CityData, route: &[usize]) -> Option<Direction> {
+    route.windows(2).find_map(|w| match find_crossing(city(city_data, w[0]), city(city_data, w[1])) {
+        Some(Ocean::Pacific) if is_america(city(city_data, w[1]).area.continent()) => Some(Direction::Eastbound),
+        Some(Ocean::Pacific) => Some(Direction::Westbound),
+        _ => None
+    })
+}
+
+// Adds the orders of `num` continents beginning with the given ones
+fn extend_orders(order: &mut Vec<Continent>, num: usize, orders: &mut Vec<Vec<Continent>>) {
+    if order.len() == num {
+        orders.push(order.clone());
+        return;
+    }
+    for c in &Continent::ALL {
+        if !order.contains(c) {
+            order.push(*c);
+            extend_orders(order, num, orders);
+            order.pop();
+        }
+    }
+}
+
+// Finds the best route from the origin for every order of the given number of continents in both directions,
+// sorted by points (the orders without any route come last)
+pub fn plan_routes(city_data: &CityData, origin: &str, num: usize, threads: usize, max_surface: Option<u32>) -> Result<Vec<PlanResult>, FindError> {
+    if !(MIN_CONTINENTS..=MAX_CONTINENTS).contains(&num) {
+        return Err(FindError::ContinentCount(num));
+    }
+    let continent = find_city(city_data, origin)?.area.continent();
+
+    let mut orders = Vec::new();
+    extend_orders(&mut vec![continent], num, &mut orders);
+
+    let mut results = Vec::new();
+    for continents in orders {
+        if !crosses_americas_once(&continents) {
+            continue;
+        }
+        let route = match find_route_auto(city_data, &continents, origin, 1, threads, max_surface) {
+            Ok(mut routes) => Some(routes.remove(0)),
+            Err(FindError::NoRoute) => None,
+            Err(e) => return Err(e)
+        };
+        let direction = route.as_ref().and_then(|r| find_direction(city_data, &r.route));
+        results.push(PlanResult { continents, direction, route });
+    }
+
+    if results.iter().all(|r| r.route.is_none()) {
+        return Err(FindError::NoRoute);
+    }
+    results.sort_by_key(|r| std::cmp::Reverse(r.route.as_ref().map(|r| r.points)));
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{fixture, region};
+
+    fn codes(city_data: &CityData, route: &[usize]) -> Vec<String> {
+        route.iter().map(|i| city(city_data, *i).code.clone()).collect()
+    }
+
+    fn continents(names: &[&str]) -> Vec<Continent> {
+        names.iter().map(|n| Continent::from_name(n).unwrap()).collect()
+    }
+
+    fn assert_valid(city_data: &CityData, results: &[RouteResult]) {
+        for r in results {
+            let surface: Vec<bool> = r.route.windows(2).map(|w| city(city_data, w[0]).route(city(city_data, w[1])).is_none()).collect();
+            let violations: Vec<String> = validate_route(city_data, &r.route, &surface, None).iter().map(|v| v.violation.to_string()).collect();
+            assert!(violations.is_empty(), "{:?}: {:?}", codes(city_data, &r.route), violations);
+        }
+    }
+
+    #[test]
+    fn auto_routes_are_valid() {
+        let world = fixture::world();
+        let cases: [(&str, &[&str]); 6] = [
+            ("HND", &["asia", "europe", "na"]),
+            ("HND", &["asia", "na", "europe"]),
+            ("HKG", &["asia", "oceania", "na", "europe"]),
+            ("LAX", &["na", "asia", "europe"]),
+            ("JFK", &["na", "europe", "asia"]),
+            ("LHR", &["europe", "africa", "sa", "na", "asia"])
+        ];
+
+        for (origin, names) in &cases {
+            let results = find_route_auto(&world, &continents(names), origin, 5, 1, None).unwrap();
+            assert_valid(&world, &results);
+        }
+    }
+
+    #[test]
+    fn auto_routes_keep_regions() {
+        // The regions are visited in both the first and the last continent
+        let mut world = fixture::world();
+        let regions = r#"[
+            {"name": "Hong Kong", "cities": ["HKG"], "max_visits": 1},
+            {"name": "Bangkok", "cities": ["BKK"], "must_return": true}
+        ]"#;
+        world.regions = region::parse_regions(regions, "test", &world.cities).unwrap();
+
+        for names in &[["asia", "europe", "na"], ["asia", "na", "europe"]] {
+            let results = find_route_auto(&world, &continents(names), "HND", 10, 1, None).unwrap();
+            assert!(!results.is_empty());
+            assert_valid(&world, &results);
+        }
+    }
+
+    // All the routes to `to` with the given number of segments, without any pruning
+    fn find_exhaustively(search: &ContinentSearch, path: &mut Vec<usize>, to: usize, remaining: usize, points: u32, results: &mut Vec<RouteResult>) {
+        let current = path[path.len() - 1];
+        if remaining == 0 {
+            if current == to {
+                add_result(results, search.top, RouteResult { points, route: path.clone() });
+            }
+            return;
+        }
+
+        for (next, d) in &search.neighbours[current] {
+            if check_rule(search.city_data, path, *next, remaining == 1) {
+                path.push(*next);
+                find_exhaustively(search, path, to, remaining - 1, points + d, results);
+                path.pop();
+            }
+        }
+    }
+
+    #[test]
+    fn pruning_keeps_best_routes() {
+        let world = fixture::world();
+        let area_sets = prepare_area_data(&world);
+        let cases = [
+            (Continent::NorthAmerica, "JFK", "LAX"),
+            (Continent::NorthAmerica, "DFW", "DFW"),
+            (Continent::Asia, "HND", "DEL"),
+            (Continent::Asia, "HKG", "NRT")
+        ];
+
+        for (continent, from, to) in &cases {
+            let from = world.find_airport(from).unwrap().index;
+            let to = world.find_airport(to).unwrap().index;
+            for (top, max_surface) in &[(1, None), (5, None), (5, Some(3000))] {
+                let mut search = ContinentSearch::new(&world, &area_sets[continent], *top, 1, *max_surface);
+                for segments in 1..=5 {
+                    let mut expected = Vec::new();
+                    find_exhaustively(&search, &mut vec![from], to, segments, 0, &mut expected);
+                    let results = search.find(from, to, segments);
+
+                    let summary = |results: &[RouteResult]| -> Vec<(u32, Vec<String>)> {
+                        results.iter().map(|r| (r.points, codes(&world, &r.route))).collect()
+                    };
+                    assert_eq!(summary(&results), summary(&expected));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn threads_give_same_results() {
+        let world = fixture::world();
+        let summary = |results: Vec<RouteResult>| -> Vec<(u32, Vec<String>)> {
+            results.into_iter().map(|r| (r.points, codes(&world, &r.route))).collect()
+        };
+
+        for (from, to, num) in &[("JFK", "LAX", 4), ("HND", "HND", 4), ("HKG", "DEL", 3)] {
+            for max_surface in &[None, Some(3000)] {
+                let single = find_continental_route(&world, from, to, *num, 5, 1, *max_surface).unwrap();
+                let multi = find_continental_route(&world, from, to, *num, 5, 4, *max_surface).unwrap();
+                assert_eq!(summary(single), summary(multi));
+            }
+        }
+
+        for (origin, names) in &[("HND", &["asia", "europe", "na"][..]), ("HKG", &["asia", "oceania", "na", "europe"])] {
+            let single = find_route_auto(&world, &continents(names), origin, 5, 1, None).unwrap();
+            let multi = find_route_auto(&world, &continents(names), origin, 5, 4, None).unwrap();
+            assert_eq!(summary(single), summary(multi));
+        }
+    }
+
+    #[test]
+    fn plan_directions() {
+        let world = fixture::world();
+        // Asia and Oceania are reached across either ocean, e.g. DEL - JFK crosses the Atlantic
+        let cases: [(&str, &[&str], Direction); 4] = [
+            ("HND", &["asia", "na", "oceania"], Direction::Westbound),
+            ("HND", &["asia", "oceania", "na"], Direction::Eastbound),
+            ("SYD", &["oceania", "asia", "na"], Direction::Westbound),
+            ("LHR", &["europe", "oceania", "na"], Direction::Eastbound)
+        ];
+
+        for (origin, names, direction) in &cases {
+            let results = plan_routes(&world, origin, names.len(), 1, None).unwrap();
+            let plan = results.iter().find(|r| r.continents == continents(names)).unwrap();
+            assert_eq!(plan.direction, Some(*direction), "{} {:?}", origin, names);
+            assert_valid(&world, plan.route.as_slice());
+        }
+    }
+
+    #[test]
+    fn bad_counts() {
+        let world = fixture::world();
+        let boundaries = ["HND", "HKG", "LHR", "MAD", "JFK", "LAX"];
+        let names = continents(&["asia", "europe", "na"]);
+        assert!(matches!(find_route(&world, &names, &boundaries, 0, 1, None), Err(FindError::TopCount)));
+        assert!(matches!(find_route_auto(&world, &names, "HND", 0, 1, None), Err(FindError::TopCount)));
+        assert!(matches!(find_continental_route(&world, "HND", "HKG", 2, 0, 1, None), Err(FindError::TopCount)));
+
+        assert!(matches!(plan_routes(&world, "HND", 2, 1, None), Err(FindError::ContinentCount(2))));
+        assert!(matches!(plan_routes(&world, "HND", 7, 1, None), Err(FindError::ContinentCount(7))));
+    }
+
+    #[test]
+    fn manual_routes() {
+        let world = fixture::world();
+        let boundaries = ["HND", "HKG", "LHR", "MAD", "JFK", "LAX"];
+        let results = find_route(&world, &continents(&["asia", "europe", "na"]), &boundaries, 5, 1, None).unwrap();
+        assert_eq!(results.len(), 5);
+        assert_valid(&world, &results);
+
+        let boundaries = ["HND", "HKG", "LHR", "MAD", "JFK", "LAX", "HKG", "HKG"];
+        assert!(matches!(find_route(&world, &continents(&["asia", "europe", "na", "asia"]), &boundaries, 5, 1, None),
+            Err(FindError::DuplicateContinent(Continent::Asia))));
+        assert!(matches!(find_route(&world, &continents(&["asia", "europe"]), &boundaries[..4], 5, 1, None),
+            Err(FindError::ContinentCount(2))));
+    }
+
+    #[test]
+    fn auto_continents() {
+        let world = fixture::world();
+        assert!(matches!(find_route_auto(&world, &continents(&["asia", "europe"]), "HND", 5, 1, None),
+            Err(FindError::ContinentCount(2))));
+        assert!(matches!(find_route_auto(&world, &continents(&["asia", "europe", "asia"]), "HND", 5, 1, None),
+            Err(FindError::DuplicateContinent(Continent::Asia))));
+        assert!(matches!(find_route_auto(&world, &continents(&["europe", "asia", "na"]), "HND", 5, 1, None),
+            Err(FindError::OriginContinent(_, Continent::EuropeMiddleEast))));
+    }
+
+    #[test]
+    fn crossings_without_points() {
+        // Routes earning nothing can still be flown between the continents
+        let mut world = fixture::world();
+        let crossings: Vec<(String, String)> = world.cities.iter()
+            .flat_map(|c| c.routes().iter().map(move |r| (c, r.to)))
+            .filter(|(c, to)| c.area.continent() != world.cities[*to].area.continent())
+            .map(|(c, to)| (c.code.clone(), world.cities[to].code.clone()))
+            .collect();
+        for (from, to) in &crossings {
+            fixture::set_points(&mut world, from, to, 0);
+        }
+        let names = continents(&["asia", "europe", "na"]);
+
+        let results = find_route(&world, &names, &["HND", "HKG", "LHR", "MAD", "JFK", "LAX"], 5, 1, None).unwrap();
+        assert_valid(&world, &results);
+        let results = find_route_auto(&world, &names, "HKG", 5, 1, None).unwrap();
+        assert_eq!(results.len(), 5);
+        assert_valid(&world, &results);
+    }
 
-    for p in longest_route {
-        print!("{} - ", city(&city_data, *p).code);
+    #[test]
+    fn return_to_origin_city() {
+        // DFW has a route to NRT but not to HND, both of which are in Tokyo
+        let world = fixture::world();
+        let names = continents(&["asia", "europe", "na"]);
+
+        let results = find_route(&world, &names, &["HND", "HKG", "LHR", "MAD", "JFK", "DFW"], 5, 1, None).unwrap();
+        assert_valid(&world, &results);
+        assert!(results.iter().all(|r| codes(&world, &r.route).last().unwrap() == "NRT"));
+
+        let results = find_route_auto(&world, &names, "HND", 20, 1, None).unwrap();
+        assert_valid(&world, &results);
+        assert!(results.iter().all(|r| city(&world, *r.route.last().unwrap()).metro == "TYO"));
+        assert!(results.iter().any(|r| codes(&world, &r.route).last().unwrap() == "NRT"));
+
+        // The return does not spend a surface sector within the city
+        let results = find_route_auto(&world, &names, "HND", 5, 1, Some(2000)).unwrap();
+        assert_valid(&world, &results);
+        assert!(results.iter().all(|r| !codes(&world, &r.route).ends_with(&[String::from("NRT"), String::from("HND")])));
     }
-    println!("");
 }
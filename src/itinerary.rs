@@ -0,0 +1,105 @@
+use super::data::CityData;
+use super::data::class::BookingClass;
+
+pub struct Segment {
+    pub from: usize,
+    pub to: usize,
+    pub class: BookingClass,
+    // Travelled by surface instead of a flight
+    pub surface: bool
+}
+
+pub struct Itinerary {
+    pub segments: Vec<Segment>
+}
+impl Itinerary {
+    // Parses a list of airport (or city) codes, each of which can be followed by ":<class>" to specify
+    // the booking class of the segment departing from the city (e.g. "HND:D LHR:J JFK").
+    // A code prefixed with "~" is reached by surface from the previous city (e.g. "NRT ~HND").
+    pub fn parse(city_data: &CityData, codes: &[String], default_class: BookingClass) -> Result<Itinerary, String> {
+        let mut stops = Vec::new();
+        for (i, code) in codes.iter().enumerate() {
+            let (code, surface) = match code.strip_prefix('~') {
+                Some(_) if i == 0 => return Err(format!("The origin cannot be reached by surface: {}", code)),
+                Some(c) => (c, true),
+                None => (code.as_str(), false)
+            };
+            let (city_code, class) = match code.split_once(':') {
+                Some((c, class)) => match BookingClass::parse(class) {
+                    Some(class) => (c, class),
+                    None => return Err(format!("Unknown booking class: {}", class))
+                },
+                None => (code, default_class)
+            };
+            match city_data.find_airport(city_code) {
+                Ok(city) => stops.push((city.index, class, surface)),
+                Err(e) => return Err(e.to_string())
+            }
+        }
+
+        let mut segments = Vec::new();
+        for i in 0..stops.len().saturating_sub(1) {
+            segments.push(Segment {
+                from: stops[i].0,
+                to: stops[i + 1].0,
+                class: stops[i].1,
+                surface: stops[i + 1].2
+            });
+        }
+
+        Ok(Itinerary { segments })
+    }
+
+    pub fn surface(&self) -> Vec<bool> {
+        self.segments.iter().map(|s| s.surface).collect()
+    }
+
+    pub fn cities(&self) -> Vec<usize> {
+        let mut cities: Vec<usize> = self.segments.iter().map(|s| s.from).collect();
+        if let Some(last) = self.segments.last() {
+            cities.push(last.to);
+        }
+        cities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::class::Cabin;
+    use crate::data::fixture;
+
+    fn parse(city_data: &CityData, codes: &str) -> Result<Itinerary, String> {
+        let codes: Vec<String> = codes.split(' ').map(String::from).collect();
+        Itinerary::parse(city_data, &codes, BookingClass::parse("Y").unwrap())
+    }
+
+    #[test]
+    fn classes() {
+        let world = fixture::world();
+        let itinerary = parse(&world, "HND:D LHR:J JFK").unwrap();
+
+        assert_eq!(itinerary.cities(), fixture::route(&world, &["HND", "LHR", "JFK"]));
+        let classes: Vec<(char, Cabin)> = itinerary.segments.iter().map(|s| (s.class.code, s.class.cabin)).collect();
+        assert_eq!(classes, vec![('D', Cabin::Business), ('J', Cabin::Business)]);
+        assert_eq!(itinerary.surface(), vec![false, false]);
+
+        // Segments without a class use the default one
+        let itinerary = parse(&world, "HND LHR:F JFK").unwrap();
+        assert_eq!(itinerary.segments.iter().map(|s| s.class.code).collect::<Vec<_>>(), vec!['Y', 'F']);
+
+        assert!(parse(&world, "HND:Z LHR").is_err());
+        assert!(parse(&world, "HND XXX").is_err());
+    }
+
+    #[test]
+    fn surface() {
+        let world = fixture::world();
+        let itinerary = parse(&world, "NRT ~HND").unwrap();
+
+        assert_eq!(itinerary.cities(), fixture::route(&world, &["NRT", "HND"]));
+        assert_eq!(itinerary.surface(), vec![true]);
+
+        assert!(parse(&world, "~NRT HND").is_err());
+    }
+}
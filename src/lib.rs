@@ -0,0 +1,7 @@
+// Library of the route finder for the oneworld Explorer fare: loading the dataset (`data`),
+// calculating the points (`data::program`, `report`), checking the rules (`rule`) and searching the routes (`find`)
+pub mod data;
+pub mod find;
+pub mod itinerary;
+pub mod report;
+pub mod rule;
@@ -1,71 +1,444 @@
-mod data;
-mod find;
-
 use std::collections::{HashMap, HashSet};
-use find::{find_route, find_intercontinental_route, find_continental_route};
-use data::{City, AreaCode};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use owe_explorer::{data, rule};
+use owe_explorer::find::{find_route, find_route_auto, find_intercontinental_route, find_continental_route, plan_routes, FindError, RouteResult,
+    IntercontinentalResult, PlanResult};
+use owe_explorer::data::Continent;
+use owe_explorer::data::check::Severity;
+use owe_explorer::data::class::BookingClass;
+use owe_explorer::data::distance::DistanceModel;
+use owe_explorer::data::program::{DistanceSource, EarningProgram, find_program, PROGRAM_NAMES};
+use owe_explorer::itinerary::Itinerary;
+use owe_explorer::report::{ItineraryReport, SegmentReport};
+
+// Exit codes
+const EXIT_NO_ROUTE: i32 = 1;
+const EXIT_BAD_INPUT: i32 = 2;
+const EXIT_DATA_ERROR: i32 = 3;
+
+/// Route finder for the oneworld Explorer fare
+#[derive(Parser)]
+#[command(name = "owe-explorer",
+    after_help = "Exit status: 0 on success, 1 if no route is found or the itinerary is invalid, 2 on bad input, 3 on dataset errors")]
+struct Cli {
+    /// Directory containing the dataset
+    #[arg(long, global = true, default_value = "data")]
+    data_dir: String,
+    /// Frequent flyer program to credit the flights to
+    #[arg(long, global = true, default_value = "jal", value_parser = PossibleValuesParser::new(PROGRAM_NAMES))]
+    program: String,
+    /// Model of the earth used to measure the distance (default: the one of the program)
+    #[arg(long, global = true, value_parser = PossibleValuesParser::new(DistanceModel::NAMES).map(|s| DistanceModel::from_name(&s).unwrap()))]
+    distance_model: Option<DistanceModel>,
+    /// Booking class letter (e.g. D) or cabin (first, business, premium, economy)
+    #[arg(long, global = true, default_value = "D", value_parser = parse_class)]
+    class: BookingClass,
+    /// Output format
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Always read the JSON files instead of the compiled dataset cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// Number of threads used by the search (default: number of available cores)
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+    /// Maximum distance in miles of a surface sector; the search uses surface sectors within a continent only if given
+    #[arg(long, global = true)]
+    max_surface: Option<u32>,
+    #[command(subcommand)]
+    command: Command
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Find the best round-the-world route through the given continents
+    Route {
+        /// Continents in the order of travel (asia, europe, oceania, africa, na, sa)
+        #[arg(long, value_delimiter = ',', default_value = "asia,europe,na")]
+        continents: Vec<String>,
+        /// Origin of the journey; the gateway cities between the continents are chosen automatically
+        #[arg(long, conflicts_with = "boundaries")]
+        origin: Option<String>,
+        /// Entry and exit cities of each continent (the entry of the first continent is the origin)
+        #[arg(required_unless_present = "origin", num_args = 2.., value_names = ["FROM", "TO"])]
+        boundaries: Vec<String>,
+        /// Number of routes to show, ranked by points
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        top: u32
+    },
+    /// Find the best route from the origin for every order of the continents, eastbound and westbound
+    Plan {
+        origin: String,
+        /// Number of continents to visit, including that of the origin
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(3..=6))]
+        continents: u32
+    },
+    /// List the longest routes between two continents (asia, europe, oceania, africa, na, sa)
+    Intercontinental {
+        area1: String,
+        area2: String
+    },
+    /// Find the best route within a continent
+    Continental {
+        from: String,
+        to: String,
+        /// Number of intermediate stops
+        num: usize,
+        /// Number of routes to show, ranked by points
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        top: u32
+    },
+    /// Calculate the points of an itinerary (e.g. HND:D LHR:J JFK, where ~ marks a city reached by surface as in NRT ~HND)
+    Mileage {
+        #[arg(required = true, num_args = 2..)]
+        cities: Vec<String>
+    },
+    /// Check an itinerary against the oneworld Explorer fare rules
+    Validate {
+        #[arg(required = true, num_args = 2..)]
+        cities: Vec<String>
+    },
+    /// Count the cities in each continent
+    Cities,
+    /// Check the dataset for missing files and inconsistencies
+    CheckData
+}
+
+fn parse_class(s: &str) -> Result<BookingClass, String> {
+    BookingClass::parse(s).ok_or_else(|| format!("unknown booking class: {}", s))
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let city_data = data::CityData::load_all_data("data");
+    let cli = Cli::parse();
 
-    if args.len() < 2 {
-        eprintln!("Usage: {} (route|intercontinental|mileage)", args[0]);
-        std::process::exit(1);
+    // The dataset is checked before it is loaded, as the check must work on a broken dataset
+    if let Command::CheckData = cli.command {
+        check_data(&cli.data_dir, cli.format);
+        return;
     }
-    match args[1].as_str() {
-        "route" => find_route(city_data, "", &[&args[2], &args[3], &args[4], &args[5], &args[6], &args[7]]),
-        "intercontinental" => find_intercontinental_route(city_data, args[2].as_str(), args[3].as_str()),
-        "continental" => find_continental_route(city_data, args[2].as_str(), args[3].as_str(), args[4].parse::<usize>().unwrap()),
-        "mileage" => calc_mileage(city_data, &args[2..]),
-        "cities" => count_cities(city_data),
-        _ => panic!("Unknown command: {}", args[1])
+
+    let program = find_program(&cli.program, cli.distance_model).unwrap();
+    let city_data = match data::CityData::load_all_data(&cli.data_dir, program.as_ref(), &cli.class, !cli.no_cache) {
+        Ok(city_data) => city_data,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(EXIT_DATA_ERROR);
+        }
+    };
+    if city_data.unknown_airlines > 0 {
+        eprintln!("Warning: {} routes have no airline data and are assumed to be flown by eligible carriers (see check-data)",
+            city_data.unknown_airlines);
     }
+    let program = program.as_ref();
+    let class = cli.class;
+    let format = cli.format;
+    let max_surface = cli.max_surface;
+    let threads = cli.threads.map(|t| t as usize)
+        .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+
+    let result = match cli.command {
+        Command::Route { continents, origin, boundaries, top } => {
+            let boundaries: Vec<&str> = boundaries.iter().map(|b| b.as_str()).collect();
+            parse_continents(&continents)
+                .and_then(|continents| match &origin {
+                    Some(origin) => find_route_auto(&city_data, &continents, origin, top as usize, threads, max_surface),
+                    None => find_route(&city_data, &continents, &boundaries, top as usize, threads, max_surface)
+                })
+                .map(|r| print_routes(&city_data, program, &class, format, &r))
+        },
+        Command::Plan { origin, continents } => plan_routes(&city_data, &origin, continents as usize, threads, max_surface)
+            .map(|r| print_plan(&city_data, program, &class, format, &r)),
+        Command::Intercontinental { area1, area2 } => find_intercontinental_route(&city_data, &area1, &area2)
+            .map(|r| print_intercontinental(&city_data, program, &class, format, &r)),
+        Command::Continental { from, to, num, top } => find_continental_route(&city_data, &from, &to, num, top as usize, threads, max_surface)
+            .map(|r| print_routes(&city_data, program, &class, format, &r)),
+        Command::Mileage { cities } => {
+            let itinerary = parse_itinerary(&city_data, &cities, class);
+            calc_mileage(&city_data, program, format, &itinerary);
+            Ok(())
+        },
+        Command::Validate { cities } => {
+            let itinerary = parse_itinerary(&city_data, &cities, class);
+            validate(&city_data, format, &itinerary, max_surface);
+            Ok(())
+        },
+        Command::Cities => {
+            count_cities(&city_data, format);
+            Ok(())
+        },
+        Command::CheckData => unreachable!()
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(match e {
+            FindError::NoRoute => EXIT_NO_ROUTE,
+            _ => EXIT_BAD_INPUT
+        });
+    }
+}
 
-    std::process::exit(0);
+fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
 }
 
-fn calc_mileage(city_data: data::CityData, cities: &[String]) {
-    let mut map = HashMap::new();
-    for i in 0..city_data.cities.len() {
-        map.insert(&city_data.cities[i].code, &city_data.cities[i]);
+fn parse_continents(names: &[String]) -> Result<Vec<Continent>, FindError> {
+    names.iter()
+        .map(|n| Continent::from_name(n).ok_or_else(|| FindError::UnknownArea(n.clone())))
+        .collect()
+}
+
+fn parse_itinerary(city_data: &data::CityData, codes: &[String], class: BookingClass) -> Itinerary {
+    match Itinerary::parse(city_data, codes, class) {
+        Ok(itinerary) => itinerary,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_BAD_INPUT);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RankedReport {
+    rank: usize,
+    // Difference of the points from the best route
+    delta: i64,
+    #[serde(flatten)]
+    itinerary: ItineraryReport
+}
+
+fn print_route(city_data: &data::CityData, route: &[usize]) {
+    for (i, p) in route.iter().enumerate() {
+        // Cities reached by surface are marked as in the itinerary
+        let surface = i > 0 && city_data.cities[route[i - 1]].route(&city_data.cities[*p]).is_none();
+        print!("{}{} - ", if surface { "~" } else { "" }, city_data.cities[*p].code);
     }
+    println!();
+}
 
-    let mut total = 0;
-    for i in 0..cities.len() - 1 {
-        let from = map[&cities[i]];
-        let to = map[&cities[i + 1]];
+fn print_routes(city_data: &data::CityData, program: &dyn EarningProgram, class: &BookingClass, format: Format, results: &[RouteResult]) {
+    let best = results[0].points as i64;
 
-        let mileage = City::calc_point(from, to);
-        total += mileage;
+    match format {
+        Format::Text => {
+            for (i, result) in results.iter().enumerate() {
+                if results.len() == 1 {
+                    println!("Distance = {}", result.points);
+                } else {
+                    println!("#{} Distance = {} ({:+})", i + 1, result.points, result.points as i64 - best);
+                }
 
-        println!("{} - {}:  {}", from.code, to.code, mileage);
+                print_route(city_data, &result.route);
+            }
+        },
+        Format::Json => {
+            let reports: Vec<RankedReport> = results.iter().enumerate().map(|(i, result)| RankedReport {
+                rank: i + 1,
+                delta: result.points as i64 - best,
+                itinerary: ItineraryReport::from_route(city_data, program, &result.route, class)
+            }).collect();
+            print_json(&reports);
+        }
     }
+}
+
+#[derive(Serialize)]
+struct PlanReport {
+    continents: Vec<Continent>,
+    // None if no route is found in the order
+    direction: Option<&'static str>,
+    itinerary: Option<ItineraryReport>
+}
 
-    println!("Total: {}", total);
-}
-
-fn count_cities(city_data: data::CityData) {
-    let mut set_asia = HashSet::new();
-    let mut set_europe = HashSet::new();
-    let mut set_oceania = HashSet::new();
-    let mut set_africa = HashSet::new();
-    let mut set_north_america = HashSet::new();
-    let mut set_south_america = HashSet::new();
-
-    for i in 0..city_data.cities.len() {
-        let city = &city_data.cities[i];
-        match city.area {
-            AreaCode::Asia | AreaCode::Japan => set_asia.insert(i),
-            AreaCode::Oceania => set_oceania.insert(i),
-            AreaCode::EuropeMiddleEast => set_europe.insert(i),
-            AreaCode::Africa => set_africa.insert(i),
-            AreaCode::NorthAmerica => set_north_america.insert(i),
-            AreaCode::SouthAmerica => set_south_america.insert(i)
-        };
+fn print_plan(city_data: &data::CityData, program: &dyn EarningProgram, class: &BookingClass, format: Format, results: &[PlanResult]) {
+    match format {
+        Format::Text => {
+            for result in results {
+                let names: Vec<&str> = result.continents.iter().map(|c| c.name()).collect();
+                if let Some(direction) = result.direction {
+                    print!("{} ", direction.name());
+                }
+                print!("{}: ", names.join(" - "));
+                match &result.route {
+                    Some(route) => {
+                        println!("{}", route.points);
+                        print_route(city_data, &route.route);
+                    },
+                    None => println!("no route")
+                }
+            }
+        },
+        Format::Json => {
+            let reports: Vec<PlanReport> = results.iter().map(|result| PlanReport {
+                continents: result.continents.clone(),
+                direction: result.direction.map(|d| d.name()),
+                itinerary: result.route.as_ref().map(|r| ItineraryReport::from_route(city_data, program, &r.route, class))
+            }).collect();
+            print_json(&reports);
+        }
     }
+}
 
-    println!("Asia => {}, Europe => {}, Oceania => {}, Africa => {}, NA => {}, SA => {}",
-        set_asia.len(), set_europe.len(), set_oceania.len(), set_africa.len(), set_north_america.len(), set_south_america.len()
-    );
+fn print_intercontinental(city_data: &data::CityData, program: &dyn EarningProgram, class: &BookingClass, format: Format, results: &[IntercontinentalResult]) {
+    match format {
+        Format::Text => {
+            for r in results {
+                println!("{} -> {}: {}", city_data.cities[r.from].code, city_data.cities[r.to].code, r.points);
+            }
+        },
+        Format::Json => {
+            let segments: Vec<SegmentReport> = results.iter()
+                .map(|r| SegmentReport::new(city_data, program, r.from, r.to, class, false))
+                .collect();
+            print_json(&segments);
+        }
+    }
+}
+
+fn calc_mileage(city_data: &data::CityData, program: &dyn EarningProgram, format: Format, itinerary: &Itinerary) {
+    let report = ItineraryReport::new(city_data, program, &itinerary.segments);
+
+    match format {
+        Format::Text => {
+            for (s, r) in itinerary.segments.iter().zip(&report.segments) {
+                if r.surface {
+                    println!("{} - {} (surface):  0", r.from, r.to);
+                    continue;
+                }
+                let airlines = if r.airlines.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", r.airlines.join(", "))
+                };
+                let source = match r.distance_source {
+                    DistanceSource::Tpm => " (TPM)",
+                    DistanceSource::Computed => ""
+                };
+                println!("{} - {} ({}, {}){}:  {}{}", r.from, r.to, s.class.code, s.class.cabin.name(), airlines, r.points, source);
+            }
+
+            println!("Total: {} ({})", report.total_points, report.program);
+        },
+        Format::Json => print_json(&report)
+    }
+}
+
+#[derive(Serialize)]
+struct ViolationReport {
+    segment: Option<usize>,
+    from: Option<String>,
+    to: Option<String>,
+    message: String
+}
+
+#[derive(Serialize)]
+struct ValidationReport {
+    valid: bool,
+    violations: Vec<ViolationReport>
+}
+
+fn validate(city_data: &data::CityData, format: Format, itinerary: &Itinerary, max_surface: Option<u32>) {
+    let route = itinerary.cities();
+
+    let violations = rule::validate_route(city_data, &route, &itinerary.surface(), max_surface);
+    let valid = violations.is_empty();
+
+    match format {
+        Format::Text => {
+            if valid {
+                println!("OK");
+            }
+            for v in &violations {
+                match v.segment {
+                    Some(i) => println!("Segment {} ({} - {}): {}", i + 1,
+                        city_data.cities[route[i]].code, city_data.cities[route[i + 1]].code, v.violation),
+                    None => println!("Itinerary: {}", v.violation)
+                }
+            }
+        },
+        Format::Json => print_json(&ValidationReport {
+            valid,
+            violations: violations.iter().map(|v| ViolationReport {
+                segment: v.segment,
+                from: v.segment.map(|i| city_data.cities[route[i]].code.clone()),
+                to: v.segment.map(|i| city_data.cities[route[i + 1]].code.clone()),
+                message: v.violation.to_string()
+            }).collect()
+        })
+    }
+
+    if !valid {
+        std::process::exit(EXIT_NO_ROUTE);
+    }
+}
+
+fn count_cities(city_data: &data::CityData, format: Format) {
+    let continents = Continent::ALL;
+    // Airports in the same city are counted once
+    let mut counts = HashMap::new();
+    let mut metros = HashSet::new();
+    for city in &city_data.cities {
+        if metros.insert(&city.metro) {
+            *counts.entry(city.area.continent()).or_insert(0) += 1;
+        }
+    }
+    let count = |c: &Continent| counts.get(c).cloned().unwrap_or(0);
+
+    match format {
+        Format::Text => println!("Asia => {}, Europe => {}, Oceania => {}, Africa => {}, NA => {}, SA => {}",
+            count(&continents[0]), count(&continents[1]), count(&continents[2]),
+            count(&continents[3]), count(&continents[4]), count(&continents[5])
+        ),
+        Format::Json => {
+            let map: serde_json::Map<String, serde_json::Value> = continents.iter()
+                .map(|c| (format!("{:?}", c), serde_json::Value::from(count(c))))
+                .collect();
+            print_json(&map);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct IssueReport {
+    severity: Severity,
+    message: String
+}
+
+fn check_data(data_dir: &str, format: Format) {
+    let issues = match data::check::check_data(data_dir) {
+        Ok(issues) => issues,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(EXIT_DATA_ERROR);
+        }
+    };
+    let errors = issues.iter().filter(|i| i.severity() == Severity::Error).count();
+
+    match format {
+        Format::Text => {
+            for issue in &issues {
+                match issue.severity() {
+                    Severity::Error => println!("error: {}", issue),
+                    Severity::Warning => println!("warning: {}", issue)
+                }
+            }
+            println!("{} errors, {} warnings", errors, issues.len() - errors);
+        },
+        Format::Json => print_json(&issues.iter().map(|i| IssueReport {
+            severity: i.severity(),
+            message: i.to_string()
+        }).collect::<Vec<IssueReport>>())
+    }
+
+    if errors > 0 {
+        std::process::exit(EXIT_DATA_ERROR);
+    }
 }
@@ -0,0 +1,89 @@
+use serde::Serialize;
+use super::data::{AreaCode, CityData};
+use super::data::class::BookingClass;
+use super::data::program::{DistanceSource, EarningProgram};
+use super::itinerary::Segment;
+use super::rule::{find_crossing, Ocean};
+
+#[derive(Serialize)]
+pub struct SegmentReport {
+    pub from: String,
+    pub to: String,
+    pub from_area: AreaCode,
+    pub to_area: AreaCode,
+    pub class: char,
+    pub distance: u32,
+    pub distance_source: DistanceSource,
+    pub multiplier: Option<f64>,
+    pub bonus: u32,
+    pub points: u32,
+    // Operating airlines of the route, if known
+    pub airlines: Vec<String>,
+    // Travelled by surface, earning no points
+    pub surface: bool,
+    // Ocean crossed by the segment, if any
+    pub crossing: Option<Ocean>
+}
+impl SegmentReport {
+    pub fn new(city_data: &CityData, program: &dyn EarningProgram, from: usize, to: usize, class: &BookingClass, surface: bool) -> SegmentReport {
+        let from = &city_data.cities[from];
+        let to = &city_data.cities[to];
+        let mut earning = program.earn(from, to, class, city_data.tpm.find(from, to));
+        if surface {
+            earning.multiplier = None;
+            earning.bonus = 0;
+            earning.points = 0;
+        }
+
+        SegmentReport {
+            from: from.code.clone(),
+            to: to.code.clone(),
+            from_area: from.area,
+            to_area: to.area,
+            class: class.code,
+            distance: earning.distance,
+            distance_source: earning.distance_source,
+            multiplier: earning.multiplier,
+            bonus: earning.bonus,
+            points: earning.points,
+            airlines: from.route(to).map(|r| r.airlines.clone()).unwrap_or_default(),
+            surface,
+            crossing: find_crossing(from, to)
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ItineraryReport {
+    pub program: &'static str,
+    pub segments: Vec<SegmentReport>,
+    pub total_distance: u32,
+    pub total_points: u32
+}
+impl ItineraryReport {
+    pub fn new(city_data: &CityData, program: &dyn EarningProgram, segments: &[Segment]) -> ItineraryReport {
+        let segments: Vec<SegmentReport> = segments.iter()
+            .map(|s| SegmentReport::new(city_data, program, s.from, s.to, &s.class, s.surface))
+            .collect();
+
+        ItineraryReport {
+            program: program.name(),
+            total_distance: segments.iter().map(|s| s.distance).sum(),
+            total_points: segments.iter().map(|s| s.points).sum(),
+            segments
+        }
+    }
+
+    // Report of a route found by the search, where every segment is booked in the same class
+    // (the segments without a flight are surface sectors)
+    pub fn from_route(city_data: &CityData, program: &dyn EarningProgram, route: &[usize], class: &BookingClass) -> ItineraryReport {
+        let segments: Vec<Segment> = route.windows(2).map(|w| Segment {
+            from: w[0],
+            to: w[1],
+            class: *class,
+            surface: city_data.cities[w[0]].route(&city_data.cities[w[1]]).is_none()
+        }).collect();
+
+        ItineraryReport::new(city_data, program, &segments)
+    }
+}
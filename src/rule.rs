@@ -0,0 +1,387 @@
+use std::collections::HashSet;
+use std::fmt;
+use serde::Serialize;
+use super::data::{CityData, City, Continent};
+use super::data::distance::DistanceModel;
+
+pub const MAX_SEGMENTS: usize = 16;
+const MAX_SEGMENTS_IN_CONTINENT: usize = 4;
+const MAX_SEGMENTS_IN_NORTH_AMERICA: usize = 6;
+pub const MIN_CONTINENTS: usize = 3;
+pub const MAX_CONTINENTS: usize = 6;
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize)]
+pub enum Ocean {
+    Atlantic,
+    Pacific
+}
+impl Ocean {
+    pub const ALL: [Ocean; 2] = [Ocean::Atlantic, Ocean::Pacific];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ocean::Atlantic => "Atlantic",
+            Ocean::Pacific => "Pacific"
+        }
+    }
+}
+
+pub enum Violation {
+    TooManySegments(usize),
+    TooManySegmentsInContinent(Continent, usize),
+    NoRoute,
+    RepeatedSegment,
+    MissingCrossing(Ocean),
+    ExtraCrossing(Ocean),
+    NotReturningToOrigin(Continent, Continent),
+    TooFewContinents(usize),
+    TooManyContinents(usize),
+    SurfaceTooLong(u32, u32),
+    SurfaceCrossing(Ocean),
+    TooManyRegionVisits(String, usize),
+    NotReturningFromRegion(String, String),
+    LeavingTerminalRegion(String)
+}
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::TooManySegments(n) =>
+                write!(f, "Too many segments: {} (max {})", n, MAX_SEGMENTS),
+            Violation::TooManySegmentsInContinent(c, n) =>
+                write!(f, "Too many segments in {}: {} (max {})", c.name(), n, max_segments_in_continent(*c)),
+            Violation::NoRoute => write!(f, "No route between the cities"),
+            Violation::RepeatedSegment => write!(f, "The same segment is flown twice in the same direction"),
+            Violation::MissingCrossing(o) => write!(f, "No {} crossing", o.name()),
+            Violation::ExtraCrossing(o) => write!(f, "More than one {} crossing", o.name()),
+            Violation::NotReturningToOrigin(origin, last) =>
+                write!(f, "The journey ends in {} instead of the origin continent {}", last.name(), origin.name()),
+            Violation::TooFewContinents(n) =>
+                write!(f, "Too few continents: {} (min {})", n, MIN_CONTINENTS),
+            Violation::TooManyContinents(n) =>
+                write!(f, "Too many continents: {} (max {})", n, MAX_CONTINENTS),
+            Violation::SurfaceTooLong(distance, max) =>
+                write!(f, "Surface sector too long: {} miles (max {})", distance, max),
+            Violation::SurfaceCrossing(o) => write!(f, "The {} cannot be crossed by surface", o.name()),
+            Violation::TooManyRegionVisits(region, max) => write!(f, "Too many visits to {} (max {})", region, max),
+            Violation::NotReturningFromRegion(region, city) => write!(f, "{} must be left to {} from which it is entered", region, city),
+            Violation::LeavingTerminalRegion(region) => write!(f, "{} can only be left at the end of the continent", region)
+        }
+    }
+}
+
+pub struct RuleViolation {
+    // Index of the offending segment (0-origin), if the violation is attributable to one
+    pub segment: Option<usize>,
+    pub violation: Violation
+}
+
+pub fn max_segments_in_continent(continent: Continent) -> usize {
+    match continent {
+        Continent::NorthAmerica => MAX_SEGMENTS_IN_NORTH_AMERICA,
+        _ => MAX_SEGMENTS_IN_CONTINENT
+    }
+}
+
+// Ocean crossed by the segment. Only the segments between the Americas and the other continents cross
+// an ocean, which is told by the way the great circle goes around the earth: the Pacific if it passes
+// the antimeridian (e.g. LAX - NRT), and the Atlantic otherwise (e.g. JFK - DEL).
+pub fn find_crossing(from: &City, to: &City) -> Option<Ocean> {
+    let is_america = |c: &City| c.area.continent() == Continent::NorthAmerica || c.area.continent() == Continent::SouthAmerica;
+    if is_america(from) == is_america(to) {
+        return None;
+    }
+
+    // Difference of the longitudes in the shorter way, in [-180, 180)
+    let d_lon = (to.lon() - from.lon() + 540.0) % 360.0 - 180.0;
+    let lon = from.lon() + d_lon;
+    if !(-180.0..=180.0).contains(&lon) {
+        Some(Ocean::Pacific)
+    } else {
+        Some(Ocean::Atlantic)
+    }
+}
+
+// Checks the constraints of the regions on the route continuing from the path to the next city,
+// where `last` tells if the segment is the last one in the continent
+pub fn check_regions(city_data: &CityData, path: &[usize], next: usize, last: bool) -> Option<Violation> {
+    let current = path[path.len() - 1];
+
+    for region in &city_data.regions {
+        if region.contains(next) && !region.contains(current) {
+            if let Some(max) = region.max_visits {
+                // Starting in the region counts as a visit
+                let visits = (0..path.len()).filter(|i| region.contains(path[*i]) && (*i == 0 || !region.contains(path[i - 1]))).count();
+                if visits >= max {
+                    return Some(Violation::TooManyRegionVisits(region.name.clone(), max));
+                }
+            }
+        } else if region.contains(current) && !region.contains(next) {
+            if region.terminal && !last {
+                return Some(Violation::LeavingTerminalRegion(region.name.clone()));
+            }
+            if region.must_return {
+                if let Some(entry) = path.iter().rev().find(|i| !region.contains(**i)) {
+                    let entry = &city_data.cities[*entry];
+                    if entry.metro != city_data.cities[next].metro {
+                        return Some(Violation::NotReturningFromRegion(region.name.clone(), entry.code.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// Distance in mile travelled on a surface sector
+pub fn surface_distance(from: &City, to: &City) -> u32 {
+    City::calc_distance(from, to, DistanceModel::Spherical).round() as u32
+}
+
+// Checks the oneworld Explorer fare rules for the itinerary given as a list of city indices,
+// and returns every violated rule. `surface` tells whether each segment is travelled by surface,
+// which counts as a segment but needs no flight, and is limited to `max_surface` miles if given.
+pub fn validate_route(city_data: &CityData, route: &[usize], surface: &[bool], max_surface: Option<u32>) -> Vec<RuleViolation> {
+    let mut violations = Vec::new();
+    if route.len() < 2 {
+        return violations;
+    }
+    let mut add = |segment: Option<usize>, violation: Violation| violations.push(RuleViolation { segment, violation });
+
+    let cities: Vec<&City> = route.iter().map(|i| &city_data.cities[*i]).collect();
+    let num_segments = cities.len() - 1;
+
+    if num_segments > MAX_SEGMENTS {
+        add(Some(MAX_SEGMENTS), Violation::TooManySegments(num_segments));
+    }
+
+    let mut continents = HashSet::new();
+    let mut segments_in_continent = Vec::new();
+    let mut crossings = Vec::new();
+
+    continents.insert(cities[0].area.continent());
+
+    for i in 0..num_segments {
+        let from = cities[i];
+        let to = cities[i + 1];
+
+        if surface[i] {
+            let distance = surface_distance(from, to);
+            if let Some(max) = max_surface.filter(|max| distance > *max) {
+                add(Some(i), Violation::SurfaceTooLong(distance, max));
+            }
+        } else if from.route(to).is_none() {
+            add(Some(i), Violation::NoRoute);
+        }
+        // Airports in the same city are treated as the same point
+        if (0..i).any(|j| cities[j].metro == from.metro && cities[j + 1].metro == to.metro) {
+            add(Some(i), Violation::RepeatedSegment);
+        }
+        // The segment is the last in the continent if the route leaves the continent after it (or on it)
+        let last = i + 1 == num_segments || from.area.continent() != to.area.continent() ||
+            cities[i + 2].area.continent() != to.area.continent();
+        if let Some(violation) = check_regions(city_data, &route[..=i], route[i + 1], last) {
+            add(Some(i), violation);
+        }
+
+        let continent = to.area.continent();
+        continents.insert(continent);
+
+        if from.area.continent() == continent {
+            segments_in_continent.push(continent);
+            let count = segments_in_continent.iter().filter(|c| **c == continent).count();
+            if count == max_segments_in_continent(continent) + 1 {
+                add(Some(i), Violation::TooManySegmentsInContinent(continent, count));
+            }
+        } else if let Some(ocean) = find_crossing(from, to) {
+            // A crossing by surface is still counted, so that the ocean is not reported as missing too
+            if surface[i] {
+                add(Some(i), Violation::SurfaceCrossing(ocean));
+            }
+            crossings.push(ocean);
+            if crossings.iter().filter(|o| **o == ocean).count() == 2 {
+                add(Some(i), Violation::ExtraCrossing(ocean));
+            }
+        }
+    }
+
+    for ocean in &Ocean::ALL {
+        if !crossings.contains(ocean) {
+            add(None, Violation::MissingCrossing(*ocean));
+        }
+    }
+
+    let origin = cities[0].area.continent();
+    let last = cities[num_segments].area.continent();
+    if origin != last {
+        add(Some(num_segments - 1), Violation::NotReturningToOrigin(origin, last));
+    }
+
+    if continents.len() < MIN_CONTINENTS {
+        add(None, Violation::TooFewContinents(continents.len()));
+    } else if continents.len() > MAX_CONTINENTS {
+        add(None, Violation::TooManyContinents(continents.len()));
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{fixture, region};
+
+    // Validates the itinerary, where a code prefixed with "~" is reached by surface
+    fn validate(city_data: &CityData, codes: &[&str]) -> Vec<String> {
+        let route = fixture::route(city_data, &codes.iter().map(|c| c.trim_start_matches('~')).collect::<Vec<&str>>());
+        let surface: Vec<bool> = codes.iter().skip(1).map(|c| c.starts_with('~')).collect();
+
+        validate_route(city_data, &route, &surface, None).iter()
+            .map(|v| format!("{:?}: {}", v.segment, v.violation))
+            .collect()
+    }
+
+    #[test]
+    fn valid_itinerary() {
+        let world = fixture::world();
+
+        assert!(validate(&world, &["HKG", "LHR", "JFK", "LAX", "HKG"]).is_empty());
+        // Four segments in Asia, six in North America
+        assert!(validate(&world, &["HKG", "BKK", "DEL", "HKG", "HND", "LAX", "JFK", "DFW", "LAX", "HNL", "OGG", "DFW", "LHR", "HKG"]).is_empty());
+    }
+
+    #[test]
+    fn no_route() {
+        let world = fixture::world();
+
+        assert_eq!(validate(&world, &["HKG", "LHR", "JFK", "LAX", "DEL", "HKG"]), vec!["Some(3): No route between the cities"]);
+    }
+
+    #[test]
+    fn route_without_points() {
+        let mut world = fixture::world();
+        fixture::set_points(&mut world, "LHR", "JFK", 0);
+
+        assert!(validate(&world, &["HKG", "LHR", "JFK", "LAX", "HKG"]).is_empty());
+    }
+
+    #[test]
+    fn segment_limits() {
+        let world = fixture::world();
+
+        assert_eq!(validate(&world, &["HKG", "BKK", "HKG", "DEL", "BKK", "HND", "LAX", "JFK", "LHR", "HKG"]),
+            vec!["Some(4): Too many segments in Asia: 5 (max 4)"]);
+        assert_eq!(validate(&world, &["HKG", "LHR", "JFK", "LAX", "DFW", "JFK", "DFW", "LAX", "OGG", "HNL", "NRT"]),
+            vec!["Some(8): Too many segments in North America: 7 (max 6)"]);
+
+        let violations = validate(&world, &[
+            "HKG", "BKK", "HKG", "DEL", "LHR", "MAD", "LHR", "DOH", "JFK", "LAX", "DFW", "JFK", "HNL", "LAX", "JFK", "DFW", "HKG", "BKK"
+        ]);
+        assert!(violations.contains(&String::from("Some(16): Too many segments: 17 (max 16)")), "{:?}", violations);
+    }
+
+    #[test]
+    fn repeated_segment() {
+        let world = fixture::world();
+
+        // Airports in the same city are the same point
+        assert_eq!(validate(&world, &["HND", "BKK", "NRT", "BKK", "DOH", "JFK", "LAX", "HND"]),
+            vec!["Some(2): The same segment is flown twice in the same direction"]);
+        // The opposite direction is another segment
+        assert!(validate(&world, &["HND", "BKK", "HND", "LAX", "JFK", "LHR", "NRT"]).is_empty());
+    }
+
+    #[test]
+    fn crossings() {
+        let world = fixture::world();
+
+        assert_eq!(validate(&world, &["HKG", "LHR", "DOH", "HKG"]), vec![
+            "None: No Atlantic crossing", "None: No Pacific crossing", "None: Too few continents: 2 (min 3)"
+        ]);
+        assert_eq!(validate(&world, &["LHR", "JFK", "MAD", "DOH", "HKG", "LAX", "DFW", "LHR"]), vec![
+            "Some(1): More than one Atlantic crossing"
+        ]);
+    }
+
+    #[test]
+    fn continents() {
+        let world = fixture::world();
+
+        assert_eq!(validate(&world, &["HKG", "LHR", "JFK", "LAX", "SYD"]),
+            vec!["Some(3): The journey ends in South West Pacific instead of the origin continent Asia"]);
+        // All the six continents
+        assert!(validate(&world, &["HKG", "SYD", "JNB", "DOH", "JFK", "GRU", "DFW", "HKG"]).is_empty());
+    }
+
+    #[test]
+    fn ocean_crossings() {
+        let world = fixture::world();
+        let crossing = |from: &str, to: &str| {
+            find_crossing(world.find_airport(from).unwrap(), world.find_airport(to).unwrap())
+        };
+
+        assert_eq!(crossing("LAX", "NRT"), Some(Ocean::Pacific));
+        assert_eq!(crossing("NRT", "LAX"), Some(Ocean::Pacific));
+        assert_eq!(crossing("JFK", "DEL"), Some(Ocean::Atlantic));
+        assert_eq!(crossing("DEL", "JFK"), Some(Ocean::Atlantic));
+        assert_eq!(crossing("HNL", "NRT"), Some(Ocean::Pacific));
+        assert_eq!(crossing("JNB", "GRU"), Some(Ocean::Atlantic));
+        assert_eq!(crossing("HKG", "LHR"), None);
+        assert_eq!(crossing("SYD", "HKG"), None);
+        assert_eq!(crossing("JFK", "GRU"), None);
+    }
+
+    // World whose regions are replaced by the given ones
+    fn with_regions(regions: &str) -> CityData {
+        let mut world = fixture::world();
+        world.regions = region::parse_regions(regions, "regions.json", &world.cities).unwrap();
+        world
+    }
+
+    #[test]
+    fn terminal_region() {
+        let world = fixture::world();
+
+        assert_eq!(validate(&world, &["HKG", "LHR", "JFK", "HNL", "DFW", "LAX", "HKG"]),
+            vec!["Some(3): Hawaii can only be left at the end of the continent"]);
+        // Returning to the mainland right before leaving the continent
+        assert!(validate(&world, &["HKG", "LHR", "JFK", "HNL", "LAX", "HKG"]).is_empty());
+        assert!(validate(&world, &["HKG", "LHR", "JFK", "HNL", "NRT"]).is_empty());
+    }
+
+    #[test]
+    fn region_visits() {
+        let world = with_regions(r#"[{"name": "Doha", "cities": ["DOH"], "max_visits": 1}]"#);
+
+        assert!(validate(&world, &["HKG", "DOH", "LHR", "JFK", "LAX", "HKG"]).is_empty());
+        assert_eq!(validate(&world, &["HKG", "DOH", "LHR", "DOH", "JFK", "LAX", "HKG"]),
+            vec!["Some(2): Too many visits to Doha (max 1)"]);
+        // Starting in the region counts as a visit
+        assert_eq!(validate(&world, &["DOH", "LHR", "DOH", "JFK", "LAX", "HKG", "DOH"]),
+            vec!["Some(1): Too many visits to Doha (max 1)", "Some(5): Too many visits to Doha (max 1)"]);
+    }
+
+    #[test]
+    fn returning_region() {
+        let world = with_regions(r#"[{"name": "London", "cities": ["LON"], "must_return": true}]"#);
+
+        assert!(validate(&world, &["HKG", "DOH", "LHR", "DOH", "JFK", "LAX", "HKG"]).is_empty());
+        assert_eq!(validate(&world, &["HKG", "DOH", "LHR", "MAD", "JFK", "LAX", "HKG"]),
+            vec!["Some(2): London must be left to DOH from which it is entered"]);
+    }
+
+    #[test]
+    fn surface() {
+        let world = fixture::world();
+
+        assert!(validate(&world, &["HND", "~NRT", "LHR", "JFK", "LAX", "HND"]).is_empty());
+        // The crossing by surface is reported once
+        assert_eq!(validate(&world, &["NRT", "LHR", "~JFK", "HND"]), vec!["Some(1): The Atlantic cannot be crossed by surface"]);
+
+        let route = fixture::route(&world, &["HND", "NRT", "LHR", "JFK", "LAX", "HND"]);
+        let violations: Vec<String> = validate_route(&world, &route, &[true, false, false, false, false], Some(10)).iter()
+            .map(|v| format!("{:?}: {}", v.segment, v.violation))
+            .collect();
+        assert_eq!(violations, vec!["Some(0): Surface sector too long: 37 miles (max 10)"]);
+    }
+}